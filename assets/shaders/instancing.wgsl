@@ -0,0 +1,55 @@
+#import bevy_pbr::mesh_view_bind_group
+#import bevy_pbr::mesh_struct
+
+[[group(1), binding(0)]]
+var<uniform> mesh: Mesh;
+
+struct Vertex {
+    [[location(0)]] position: vec3<f32>;
+    [[location(1)]] normal: vec3<f32>;
+    [[location(2)]] uv: vec2<f32>;
+
+    [[location(3)]] i_pos_scale: vec4<f32>;
+    [[location(4)]] i_color: vec4<f32>;
+    [[location(5)]] i_up: vec3<f32>;
+};
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] color: vec4<f32>;
+    [[location(1)]] world_normal: vec3<f32>;
+};
+
+[[stage(vertex)]]
+fn vertex(vertex: Vertex) -> VertexOutput {
+    // Turn the mesh so its y axis points up, using any x axis square to it
+    let height = length(vertex.i_up);
+    let y = vertex.i_up / height;
+    var helper = vec3<f32>(1.0, 0.0, 0.0);
+    if (abs(y.x) > 0.9) {
+        helper = vec3<f32>(0.0, 0.0, 1.0);
+    }
+    let z = normalize(cross(helper, y));
+    let x = cross(y, z);
+    let scale = vertex.i_pos_scale.w;
+
+    let local = vertex.position;
+    let position = x * local.x * scale + y * local.y * height + z * local.z * scale + vertex.i_pos_scale.xyz;
+    let world_position = mesh.model * vec4<f32>(position, 1.0);
+
+    var out: VertexOutput;
+    out.clip_position = view.view_proj * world_position;
+    out.color = vertex.i_color;
+    // Stretching along y squashes the normals the other way
+    let normal = x * vertex.normal.x / scale + y * vertex.normal.y / height + z * vertex.normal.z / scale;
+    out.world_normal = (mesh.model * vec4<f32>(normal, 0.0)).xyz;
+    return out;
+}
+
+[[stage(fragment)]]
+fn fragment(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    // A fixed light from above and in front, like the Theater's, so the points still look round
+    let light = normalize(vec3<f32>(0.3, 1.0, 0.5));
+    let diffuse = max(dot(normalize(in.world_normal), light), 0.0);
+    return vec4<f32>(in.color.rgb * (0.35 + 0.65 * diffuse), in.color.a);
+}
@@ -0,0 +1,104 @@
+//! Compare one entity per point against one instanced batch, for plots of a few sizes.
+//!
+//! Frames are drawn for real, offscreen, so this needs a GPU but no display.
+use avis::feature::Pipe;
+use avis::palette::Palette;
+use avis::scatterplot::Scatterplot;
+use avis::theater::{Axis, Theater};
+use bevy::prelude::*;
+use bevy::render::camera::RenderTarget;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat, TextureUsages};
+use bevy::render::texture::BevyDefault;
+use bevy::winit::WinitPlugin;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use rand::prelude::*;
+
+/// Random points spread through the whole Theater
+fn random_plot(count: usize, instanced: bool) -> Scatterplot {
+    let mut rng = StdRng::seed_from_u64(count as u64);
+    let mut axis = |axis: Axis| {
+        let content = (0..count).map(|_| rng.gen::<f32>()).collect::<Vec<_>>();
+        Pipe::from(&content[..]).bundle(content).on(axis)
+    };
+    let (x, y, z) = (axis(Axis::Width), axis(Axis::Height), axis(Axis::Depth));
+    let sizes = vec![0.0; count];
+    let palette = Palette::viridis();
+    Scatterplot {
+        x,
+        y,
+        z,
+        sizes: Pipe::new(0.0..=1.0, 0.02..=0.02).bundle(sizes),
+        colors: (0..count).map(|i| palette.sample(i as f32 / count as f32)).collect(),
+        instanced,
+        ..Scatterplot::default()
+    }
+}
+
+/// Everything a Scatterplot needs to set up, short of a window and a GPU, to time spawning alone
+fn headless_app(plot: Scatterplot) -> App {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .add_plugin(TransformPlugin)
+        .add_plugin(bevy::asset::AssetPlugin)
+        .add_asset::<Mesh>()
+        .add_asset::<StandardMaterial>()
+        .insert_resource(Theater::default())
+        .add_plugin(plot);
+    app
+}
+
+/// A Scatterplot drawn by a camera into an image, so each update renders a whole frame
+fn rendering_app(plot: Scatterplot) -> App {
+    let mut app = App::new();
+    app.add_plugins_with(DefaultPlugins, |group| group.disable::<WinitPlugin>())
+        .insert_resource(Theater::default())
+        .add_plugin(plot);
+    let size = Extent3d {
+        width: 1280,
+        height: 720,
+        depth_or_array_layers: 1,
+    };
+    let mut image = Image::new_fill(size, TextureDimension::D2, &[0, 0, 0, 255], TextureFormat::bevy_default());
+    image.texture_descriptor.usage = TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING;
+    let image = app.world.resource_mut::<Assets<Image>>().add(image);
+    let mut camera = PerspectiveCameraBundle {
+        transform: Transform::from_xyz(1.0, 2.0, 8.0).looking_at(Vec3::new(0.0, 2.5, 0.0), Vec3::Y),
+        ..Default::default()
+    };
+    camera.camera.target = RenderTarget::Image(image);
+    app.world.spawn().insert_bundle(camera);
+    app.world.spawn().insert_bundle(DirectionalLightBundle::default());
+    // Let the shaders load and the pipelines compile before anything is timed
+    for _ in 0..30 {
+        app.update();
+    }
+    app
+}
+
+fn bench_setup(c: &mut Criterion) {
+    let mut group = c.benchmark_group("setup");
+    group.sample_size(10);
+    for count in [1_000, 10_000, 100_000] {
+        for (mode, instanced) in [("entities", false), ("instanced", true)] {
+            let plot = random_plot(count, instanced);
+            group.bench_with_input(BenchmarkId::new(mode, count), &plot, |b, plot| {
+                b.iter(|| headless_app(plot.clone()).update())
+            });
+        }
+    }
+    group.finish();
+}
+
+fn bench_frame(c: &mut Criterion) {
+    let mut group = c.benchmark_group("frame");
+    for count in [1_000, 10_000, 100_000] {
+        for (mode, instanced) in [("entities", false), ("instanced", true)] {
+            let mut app = rendering_app(random_plot(count, instanced));
+            group.bench_function(BenchmarkId::new(mode, count), |b| b.iter(|| app.update()));
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_setup, bench_frame);
+criterion_main!(benches);
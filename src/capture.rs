@@ -0,0 +1,464 @@
+use std::f32::consts::TAU;
+use std::num::NonZeroU32;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use bevy::app::{AppExit, ScheduleRunnerPlugin, ScheduleRunnerSettings};
+use bevy::asset::{HandleId, LoadState};
+use bevy::core_pipeline::node::MAIN_PASS_DRIVER;
+use bevy::core_pipeline::{AlphaMask3d, Opaque3d, Transparent3d};
+use bevy::prelude::*;
+use bevy::render::camera::RenderTarget;
+use bevy::render::render_asset::RenderAssets;
+use bevy::render::render_graph::{self, NodeRunError, RenderGraph, RenderGraphContext};
+use bevy::render::render_phase::{CachedRenderPipelinePhaseItem, RenderPhase};
+use bevy::render::render_resource::{
+    Buffer, BufferDescriptor, BufferUsages, CachedPipelineState, Extent3d, ImageCopyBuffer, ImageDataLayout, MapMode,
+    PipelineCache, TextureDimension, TextureFormat, TextureUsages,
+};
+use bevy::render::renderer::{RenderContext, RenderDevice};
+use bevy::render::texture::BevyDefault;
+use bevy::render::{RenderApp, RenderStage};
+use bevy::transform::TransformSystem;
+use bevy::ui::node::UI_PASS_DRIVER;
+use bevy::ui::TransparentUi;
+use bevy::winit::WinitPlugin;
+use bevy_text_mesh::prelude::TextMesh;
+
+use crate::clock::Clock;
+use crate::theater::{Axis, Theater};
+
+/// Updates to wait at most for everything to load, before capturing whatever there is
+const MOST_WARMUP_UPDATES: usize = 600;
+
+/// Render to PNG files instead of a window, then exit. This works without a display.
+///
+/// Add it before the Theater, which leaves the window out when it sees a Capture. Every camera
+/// draws into the image, from wherever it starts, unless there's an Orbit to follow.
+#[derive(Debug, Clone)]
+pub struct Capture {
+    /// Where to write the image. With several frames, each is numbered, like frame-0001.png.
+    pub output: PathBuf,
+    /// How many frames to write
+    pub frames: usize,
+    /// The size of each image, in pixels
+    pub size: UVec2,
+    /// How many frames make a second of the clip
+    pub fps: f32,
+    /// A path for the camera to follow over the frames
+    pub orbit: Option<Orbit>,
+}
+
+/// A circle around the Theater for the camera to follow while capturing, looking inward, like a
+/// turntable.
+///
+/// Where the camera is depends only on the frame number, so the clip comes out the same however
+/// long each frame takes to draw.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Orbit {
+    /// How far out from the center to circle, or else far enough to take in the whole stage
+    pub radius: Option<f32>,
+    /// How far above the center to circle, or else most of the way up the stage
+    pub height: Option<f32>,
+    /// How many times around over the whole clip
+    pub turns: f32,
+}
+impl Default for Orbit {
+    /// Once around the stage
+    fn default() -> Self {
+        Orbit {
+            radius: None,
+            height: None,
+            turns: 1.0,
+        }
+    }
+}
+impl Orbit {
+    /// The point the camera circles and looks at: the middle of the stage, a little off the floor
+    pub fn center(theater: &Theater) -> Vec3 {
+        let at = |axis: Axis, fraction: f32| *theater.part(axis, fraction..=fraction).start();
+        Vec3::new(at(Axis::Width, 0.5), at(Axis::Height, 0.2), at(Axis::Depth, 0.5))
+    }
+
+    /// Where the camera is some fraction of the way through the clip
+    pub fn at(&self, theater: &Theater, fraction: f32) -> Transform {
+        let span = |axis: Axis| theater.range(axis).end() - theater.range(axis).start();
+        let radius = self.radius.unwrap_or(0.8 * span(Axis::Width).max(span(Axis::Depth)));
+        let height = self.height.unwrap_or(0.8 * span(Axis::Height));
+        let center = Orbit::center(theater);
+        let angle = TAU * self.turns * fraction;
+        let offset = Vec3::new(radius * angle.sin(), height, radius * angle.cos());
+        Transform::from_translation(center + offset).looking_at(center, Vec3::Y)
+    }
+}
+
+impl Capture {
+    /// Write one frame at 1280 by 720
+    pub fn new(output: impl Into<PathBuf>) -> Self {
+        Capture {
+            output: output.into(),
+            frames: 1,
+            size: UVec2::new(1280, 720),
+            fps: 30.0,
+            orbit: None,
+        }
+    }
+
+    /// Circle the camera for some seconds, writing a frame for every step along the way
+    pub fn turntable(mut self, seconds: f32, fps: f32) -> Self {
+        self.frames = ((seconds * fps).round() as usize).max(1);
+        self.fps = fps;
+        self.orbit.get_or_insert_with(Orbit::default);
+        self
+    }
+
+    /// Where to write a frame: the output itself for a single frame, or numbered after it
+    pub fn path(&self, index: usize) -> PathBuf {
+        if self.frames <= 1 {
+            return self.output.clone();
+        }
+        let stem = self.output.file_stem().unwrap_or_default().to_string_lossy();
+        let extension = self.output.extension().map_or("png".into(), |e| e.to_string_lossy());
+        self.output.with_file_name(format!("{}-{:04}.{}", stem, index + 1, extension))
+    }
+}
+
+/// The image every camera draws into, and the buffer it's copied to so it can be read back
+#[derive(Clone)]
+struct CaptureTarget {
+    image: Handle<Image>,
+    buffer: Buffer,
+    size: UVec2,
+    format: TextureFormat,
+}
+
+/// Counts updates, so each frame is drawn according to its number rather than the wall clock
+#[derive(Default)]
+struct Updates {
+    count: usize,
+    /// The update that draws the first frame, once everything has loaded
+    start: Option<usize>,
+}
+impl Updates {
+    /// The frame being drawn in this update, once the warmup is over
+    fn drawing(&self) -> Option<usize> {
+        self.count.checked_sub(self.start?)
+    }
+    /// The frame to save in this update, which was drawn in the last one
+    fn saving(&self) -> Option<usize> {
+        self.drawing()?.checked_sub(1)
+    }
+}
+
+/// Whether everything drawn in the last frame had its pipeline built, as the render world last saw
+#[derive(Clone, Default)]
+struct PipelinesReady(Arc<AtomicBool>);
+
+/// Copies to buffers have each row padded out to 256 bytes, so this takes the padding back out,
+/// and puts the channels in RGBA order, which is how the image is written
+fn unpad(padded: &[u8], size: UVec2, format: TextureFormat) -> Vec<u8> {
+    let row = size.x as usize * 4;
+    let bgra = matches!(format, TextureFormat::Bgra8Unorm | TextureFormat::Bgra8UnormSrgb);
+    padded
+        .chunks(RenderDevice::align_copy_bytes_per_row(row))
+        .take(size.y as usize)
+        .flat_map(|padded_row| padded_row[..row].chunks_exact(4))
+        .flat_map(|pixel| match bgra {
+            true => [pixel[2], pixel[1], pixel[0], pixel[3]],
+            false => [pixel[0], pixel[1], pixel[2], pixel[3]],
+        })
+        .collect()
+}
+
+impl Plugin for Capture {
+    fn build(&self, app: &mut App) {
+        // Without winit there's no window, and the app runs until it has every frame, as fast as
+        // it can. Animations go by the Clock, which moves one frame's worth each update.
+        app.insert_resource(self.clone())
+            .init_resource::<Updates>()
+            .init_resource::<PipelinesReady>()
+            .insert_resource(Clock::driven())
+            .add_plugins_with(DefaultPlugins, |group| group.disable::<WinitPlugin>())
+            .insert_resource(ScheduleRunnerSettings::run_loop(Duration::ZERO))
+            .add_plugin(ScheduleRunnerPlugin);
+
+        let size = Extent3d {
+            width: self.size.x,
+            height: self.size.y,
+            depth_or_array_layers: 1,
+        };
+        let mut image = Image::new_fill(size, TextureDimension::D2, &[0, 0, 0, 255], TextureFormat::bevy_default());
+        image.texture_descriptor.usage =
+            TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_SRC | TextureUsages::TEXTURE_BINDING;
+        let image = app.world.resource_mut::<Assets<Image>>().add(image);
+        let row = RenderDevice::align_copy_bytes_per_row(self.size.x as usize * 4);
+        let buffer = app.world.resource::<RenderDevice>().create_buffer(&BufferDescriptor {
+            label: Some("capture_buffer"),
+            size: (row * self.size.y as usize) as u64,
+            usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let target = CaptureTarget {
+            image,
+            buffer,
+            size: self.size,
+            format: TextureFormat::bevy_default(),
+        };
+
+        let ready = app.world.resource::<PipelinesReady>().clone();
+        app.insert_resource(target.clone())
+            .add_system_to_stage(CoreStage::PreUpdate, aim_cameras)
+            .add_system_to_stage(CoreStage::First, save_frames)
+            .add_system_to_stage(CoreStage::First, start_when_ready.after(save_frames))
+            .add_system_to_stage(CoreStage::First, drive_clock.after(start_when_ready));
+        if self.orbit.is_some() {
+            // After anything else that moves the camera, like the fly camera, and before it's drawn
+            app.add_system_to_stage(
+                CoreStage::PostUpdate,
+                follow_orbit.before(TransformSystem::TransformPropagate),
+            );
+        }
+        if let Ok(render_app) = app.get_sub_app_mut(RenderApp) {
+            render_app
+                .insert_resource(target)
+                .insert_resource(ready)
+                .add_system_to_stage(RenderStage::PhaseSort, check_pipelines);
+            let mut graph = render_app.world.resource_mut::<RenderGraph>();
+            graph.add_node(CAPTURE_NODE, CaptureNode);
+            graph
+                .add_node_edge(MAIN_PASS_DRIVER, CAPTURE_NODE)
+                .expect("The main pass is missing");
+            // The overlay is drawn after the main pass, and it should be in the picture too
+            let _ = graph.add_node_edge(UI_PASS_DRIVER, CAPTURE_NODE);
+        }
+    }
+}
+
+/// Draw through every camera into the image instead of a window
+fn aim_cameras(target: Res<CaptureTarget>, mut cameras: Query<&mut Camera, Added<Camera>>) {
+    for mut camera in cameras.iter_mut() {
+        camera.target = RenderTarget::Image(target.image.clone());
+    }
+}
+
+/// Start drawing frames once every font, texture and scene has loaded and everything on screen has
+/// its pipeline, so the first frame isn't missing anything that's still on its way
+#[allow(clippy::too_many_arguments)]
+fn start_when_ready(
+    mut updates: ResMut<Updates>,
+    asset_server: Res<AssetServer>,
+    ready: Res<PipelinesReady>,
+    materials: Res<Assets<StandardMaterial>>,
+    texts: Query<&Text>,
+    text_meshes: Query<&TextMesh>,
+    scenes: Query<&Handle<Scene>>,
+    surfaces: Query<&Handle<StandardMaterial>>,
+) {
+    if updates.start.is_some() {
+        return;
+    }
+    let fonts = texts.iter().flat_map(|text| &text.sections).map(|section| section.style.font.id);
+    let textures = surfaces
+        .iter()
+        .filter_map(|material| materials.get(material)?.base_color_texture.as_ref())
+        .map(|texture| texture.id);
+    let mut handles = fonts
+        .chain(text_meshes.iter().map(|text| text.style.font.id))
+        .chain(scenes.iter().map(|scene| scene.id))
+        .chain(textures);
+    // Assets that failed won't ever arrive, and ones made in code were never loading
+    let loaded = handles.all(|handle: HandleId| asset_server.get_load_state(handle) != LoadState::Loading);
+    if loaded && ready.0.load(Ordering::Acquire) {
+        updates.start = Some(updates.count);
+    } else if updates.count >= MOST_WARMUP_UPDATES {
+        warn!("Still loading after {} updates, so capturing anyway", updates.count);
+        updates.start = Some(updates.count);
+    }
+}
+
+/// Note whether every pipeline drawn with has been built yet
+fn check_pipelines(
+    ready: Res<PipelinesReady>,
+    pipelines: Res<PipelineCache>,
+    opaque: Query<&RenderPhase<Opaque3d>>,
+    masked: Query<&RenderPhase<AlphaMask3d>>,
+    transparent: Query<&RenderPhase<Transparent3d>>,
+    ui: Query<&RenderPhase<TransparentUi>>,
+) {
+    fn built<T: CachedRenderPipelinePhaseItem>(pipelines: &PipelineCache, phase: &RenderPhase<T>) -> bool {
+        (phase.items.iter())
+            .all(|item| matches!(pipelines.get_render_pipeline_state(item.cached_pipeline()), CachedPipelineState::Ok(_)))
+    }
+    let all_built = opaque.iter().all(|phase| built(&pipelines, phase))
+        && masked.iter().all(|phase| built(&pipelines, phase))
+        && transparent.iter().all(|phase| built(&pipelines, phase))
+        && ui.iter().all(|phase| built(&pipelines, phase));
+    ready.0.store(all_built, Ordering::Release);
+}
+
+/// Set the Clock to the time of the frame being drawn, holding still at the start while warming up
+fn drive_clock(capture: Res<Capture>, updates: Res<Updates>, mut clock: ResMut<Clock>) {
+    clock.set(updates.drawing().unwrap_or(0) as f64 / capture.fps as f64);
+}
+
+/// Put every 3D camera where the orbit is for the frame being drawn, and at the start while
+/// warming up. The overlay's camera stays where it is.
+fn follow_orbit(
+    capture: Res<Capture>,
+    updates: Res<Updates>,
+    theater: Option<Res<Theater>>,
+    mut cameras: Query<&mut Transform, With<PerspectiveProjection>>,
+) {
+    let orbit = match capture.orbit {
+        Some(orbit) => orbit,
+        None => return,
+    };
+    let stage = Theater::default();
+    let theater = theater.as_deref().unwrap_or(&stage);
+    // The last frame stops a step short of the first, so the clip loops without a stutter
+    let fraction = updates.drawing().unwrap_or(0) as f32 / capture.frames as f32;
+    for mut transform in cameras.iter_mut() {
+        *transform = orbit.at(theater, fraction);
+    }
+}
+
+/// Write each frame once it's rendered, and exit after the last one
+fn save_frames(
+    capture: Res<Capture>,
+    target: Res<CaptureTarget>,
+    device: Res<RenderDevice>,
+    mut updates: ResMut<Updates>,
+    mut exit: EventWriter<AppExit>,
+) {
+    // The buffer holds whatever was rendered at the end of the last update
+    updates.count += 1;
+    let index = match updates.saving() {
+        Some(index) => index,
+        None => return,
+    };
+    let slice = target.buffer.slice(..);
+    device.map_buffer(&slice, MapMode::Read);
+    let pixels = unpad(&slice.get_mapped_range(), target.size, target.format);
+    target.buffer.unmap();
+
+    let path = capture.path(index);
+    let (width, height) = (target.size.x, target.size.y);
+    if let Err(error) = image::save_buffer(&path, &pixels, width, height, image::ColorType::Rgba8) {
+        error!("Couldn't write {}: {}", path.display(), error);
+        exit.send(AppExit);
+    } else if index + 1 >= capture.frames {
+        exit.send(AppExit);
+    }
+}
+
+const CAPTURE_NODE: &str = "capture";
+
+/// Copies the image to the buffer after everything's drawn
+struct CaptureNode;
+impl render_graph::Node for CaptureNode {
+    fn run(
+        &self,
+        _graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        let target = world.resource::<CaptureTarget>();
+        // The image reaches the GPU a frame after it's added
+        if let Some(image) = world.resource::<RenderAssets<Image>>().get(&target.image) {
+            let row = RenderDevice::align_copy_bytes_per_row(target.size.x as usize * 4);
+            render_context.command_encoder.copy_texture_to_buffer(
+                image.texture.as_image_copy(),
+                ImageCopyBuffer {
+                    buffer: &target.buffer,
+                    layout: ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: NonZeroU32::new(row as u32),
+                        rows_per_image: None,
+                    },
+                },
+                Extent3d {
+                    width: target.size.x,
+                    height: target.size.y,
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_capture_paths() {
+    let mut capture = Capture::new("out/frame.png");
+    assert_eq!(capture.path(0), PathBuf::from("out/frame.png"));
+    capture.frames = 3;
+    assert_eq!(capture.path(0), PathBuf::from("out/frame-0001.png"));
+    assert_eq!(capture.path(2), PathBuf::from("out/frame-0003.png"));
+}
+
+#[test]
+fn test_turntable() {
+    let capture = Capture::new("out/spin.png").turntable(2.0, 24.0);
+    assert_eq!(capture.frames, 48);
+    assert_eq!(capture.path(47), PathBuf::from("out/spin-0048.png"));
+    let orbit = capture.orbit.unwrap();
+    let theater = Theater::default();
+    let center = Orbit::center(&theater);
+    assert_eq!(center, Vec3::new(0.0, 1.0, 0.0));
+    // Halfway around is straight across from the start, at the same height
+    let (start, half) = (orbit.at(&theater, 0.0).translation, orbit.at(&theater, 0.5).translation);
+    assert!((start - center - Vec3::new(0.0, 4.0, 8.0)).length() < 1e-4);
+    assert!((half - center - Vec3::new(0.0, 4.0, -8.0)).length() < 1e-4);
+    assert!((orbit.at(&theater, 1.0).translation - start).length() < 1e-4);
+    // Always facing the center
+    let facing = orbit.at(&theater, 0.3).forward();
+    let inward = (center - orbit.at(&theater, 0.3).translation).normalize();
+    assert!(facing.dot(inward) > 0.9999);
+
+    // A bigger stage is circled from further out, unless the orbit says otherwise
+    let big = Theater {
+        width: -10.0..=10.0,
+        height: 0.0..=10.0,
+        depth: -20.0..=20.0,
+    };
+    assert!((orbit.at(&big, 0.0).translation - Vec3::new(0.0, 10.0, 32.0)).length() < 1e-4);
+    let close = Orbit {
+        radius: Some(3.0),
+        height: Some(1.0),
+        ..orbit
+    };
+    assert!((close.at(&big, 0.0).translation - Vec3::new(0.0, 3.0, 3.0)).length() < 1e-4);
+}
+
+#[test]
+fn test_unpad() {
+    // Two rows of one pixel each, padded out to 256 bytes
+    let mut padded = vec![0; 512];
+    padded[..4].copy_from_slice(&[1, 2, 3, 4]);
+    padded[256..260].copy_from_slice(&[5, 6, 7, 8]);
+    assert_eq!(
+        unpad(&padded, UVec2::new(1, 2), TextureFormat::Rgba8UnormSrgb),
+        vec![1, 2, 3, 4, 5, 6, 7, 8]
+    );
+    // Desktops usually render in BGRA, which has red and blue the other way around
+    assert_eq!(
+        unpad(&padded, UVec2::new(1, 2), TextureFormat::Bgra8UnormSrgb),
+        vec![3, 2, 1, 4, 7, 6, 5, 8]
+    );
+}
+
+#[test]
+fn test_updates() {
+    let mut updates = Updates {
+        count: 3,
+        start: None,
+    };
+    assert_eq!((updates.drawing(), updates.saving()), (None, None));
+    // Drawing starts when everything's ready, and saving follows a frame behind
+    updates.start = Some(3);
+    assert_eq!((updates.drawing(), updates.saving()), (Some(0), None));
+    updates.count = 5;
+    assert_eq!((updates.drawing(), updates.saving()), (Some(2), Some(1)));
+}
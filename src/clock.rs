@@ -0,0 +1,67 @@
+use bevy::prelude::*;
+
+/// How far animations have gotten, in seconds. It keeps up with Time, unless something else drives
+/// it: a Capture steps it one frame at a time, so clips come out the same however long each frame
+/// takes to draw.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Clock {
+    delta: f32,
+    elapsed: f64,
+    driven: bool,
+}
+impl Clock {
+    /// A clock that only moves when it's set
+    pub fn driven() -> Self {
+        Clock {
+            driven: true,
+            ..Default::default()
+        }
+    }
+
+    /// Move the clock to a time. The step from where it was is this update's delta.
+    pub fn set(&mut self, seconds: f64) {
+        self.delta = (seconds - self.elapsed) as f32;
+        self.elapsed = seconds;
+    }
+
+    /// How far the clock moved in this update
+    pub fn delta_seconds(&self) -> f32 {
+        self.delta
+    }
+
+    pub fn seconds_since_startup(&self) -> f64 {
+        self.elapsed
+    }
+}
+
+/// Keeps the Clock up with Time. Anything animated adds it, and it's only set up once.
+pub struct ClockPlugin;
+impl Plugin for ClockPlugin {
+    fn build(&self, app: &mut App) {
+        if app.world.contains_resource::<Clock>() {
+            return;
+        }
+        app.init_resource::<Clock>().add_system_to_stage(CoreStage::First, follow_time);
+    }
+}
+
+fn follow_time(time: Res<Time>, mut clock: ResMut<Clock>) {
+    if !clock.driven {
+        let seconds = clock.elapsed + time.delta_seconds_f64();
+        clock.set(seconds);
+    }
+}
+
+#[test]
+fn test_driven_clock() {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .insert_resource(Clock::driven())
+        .add_plugin(ClockPlugin);
+    app.update();
+    app.update();
+    assert_eq!(app.world.resource::<Clock>().seconds_since_startup(), 0.0);
+    app.world.resource_mut::<Clock>().set(0.5);
+    let clock = app.world.resource::<Clock>();
+    assert_eq!((clock.delta_seconds(), clock.seconds_since_startup()), (0.5, 0.5));
+}
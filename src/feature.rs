@@ -1,16 +1,63 @@
+use bevy::prelude::Color;
 use std::ops::RangeInclusive;
 
+use crate::theater::Axis;
+
 /// An f32 vector combined with a transformation to display it
 #[derive(Debug, Clone)]
 pub struct Feature {
     pub content: Vec<f32>,
     pub pipe: Pipe,
+    /// The Theater axis this feature fills, if any. Its pipe is fit to it when it's shown.
+    pub axis: Option<Axis>,
+    /// The content at each frame of a Timeline, if it changes over time. The first is `content`.
+    pub frames: Vec<Vec<f32>>,
 }
 impl Feature {
+    /// Spread this feature along an axis of the Theater, whatever its range
+    pub fn on(mut self, axis: Axis) -> Self {
+        self.axis = Some(axis);
+        self
+    }
     pub fn convert(&self) -> Vec<f32> {
         // These are usually tiny data, don't worry about inefficiency here
         self.content.iter().map(|v| self.pipe.apply(*v)).collect()
     }
+    /// Give the feature different content at each frame of a Timeline
+    pub fn frames(mut self, frames: Vec<Vec<f32>>) -> Self {
+        if let Some(first) = frames.first() {
+            self.content = first.clone();
+        }
+        self.frames = frames;
+        self
+    }
+    /// Whether the content changes over time
+    pub fn is_animated(&self) -> bool {
+        self.frames.len() > 1
+    }
+    /// The content at a fractional frame, blended between the frames on either side.
+    /// Missing values can't be blended, so they come from the nearer frame.
+    pub fn content_at(&self, frame: f32) -> Vec<f32> {
+        if !self.is_animated() {
+            return self.content.clone();
+        }
+        let frame = frame.clamp(0.0, (self.frames.len() - 1) as f32);
+        let (before, after) = (&self.frames[frame.floor() as usize], &self.frames[frame.ceil() as usize]);
+        let t = frame.fract();
+        before
+            .iter()
+            .zip(after)
+            .map(|(&a, &b)| match (a.is_finite(), b.is_finite()) {
+                (true, true) => a + (b - a) * t,
+                _ if t < 0.5 => a,
+                _ => b,
+            })
+            .collect()
+    }
+    /// Convert the content at a fractional frame
+    pub fn convert_at(&self, frame: f32) -> Vec<f32> {
+        self.content_at(frame).iter().map(|v| self.pipe.apply(*v)).collect()
+    }
 }
 
 /// A transformation from an input space to the display space (e.g. width of the Theater in meters)
@@ -19,6 +66,7 @@ pub struct Pipe {
     domain: RangeInclusive<f32>,
     range: RangeInclusive<f32>,
     overflow: Overflow,
+    scale: Scale,
 }
 
 /// How should we handle out of bounds values? Clamp them, or allow them to overflow the range?
@@ -28,6 +76,44 @@ pub enum Overflow {
     Saturate,
 }
 
+/// How values are spaced out before they are mapped linearly onto the range.
+/// Skewed data like populations usually reads better on a log or sqrt scale.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scale {
+    Linear,
+    /// Logarithm with the given base. Only positive values have a place on this scale.
+    Log(f32),
+    /// Raise to the given exponent, e.g. 0.5 for a square root. Negative values keep their sign.
+    Pow(f32),
+    /// Symmetric log: nearly linear within the given constant of zero, logarithmic beyond it.
+    /// Unlike Log, this handles zero and negative values.
+    Symlog(f32),
+}
+impl Scale {
+    /// Move a value from the data space into the (still unscaled) linear space
+    pub fn forward(&self, val: f32) -> f32 {
+        match *self {
+            Scale::Linear => val,
+            Scale::Log(base) => val.log(base),
+            Scale::Pow(exponent) => val.signum() * val.abs().powf(exponent),
+            Scale::Symlog(constant) => val.signum() * (val / constant).abs().ln_1p(),
+        }
+    }
+    /// The inverse of forward
+    pub fn backward(&self, val: f32) -> f32 {
+        match *self {
+            Scale::Linear => val,
+            Scale::Log(base) => base.powf(val),
+            Scale::Pow(exponent) => val.signum() * val.abs().powf(exponent.recip()),
+            Scale::Symlog(constant) => val.signum() * val.abs().exp_m1() * constant,
+        }
+    }
+    /// Whether this value has a place on this scale (e.g. log scales can't show zero)
+    pub fn accepts(&self, val: f32) -> bool {
+        self.forward(val).is_finite()
+    }
+}
+
 /// Create a pipe from a set of values as the domain
 impl From<&[f32]> for Pipe {
     fn from(content: &[f32]) -> Self {
@@ -41,15 +127,23 @@ impl Pipe {
             domain,
             range,
             overflow: Overflow::Extend,
+            scale: Scale::Linear,
         }
     }
     pub fn overflow(mut self, overflow: Overflow) -> Self {
         self.overflow = overflow;
         self
     }
-    pub fn infer_domain(mut self, content: &[f32]) -> Self {
-        let min = content.iter().copied().reduce(f32::min).unwrap_or(f32::MIN);
-        let mut max = content.iter().copied().reduce(f32::max).unwrap_or(f32::MAX);
+    pub fn scale(mut self, scale: Scale) -> Self {
+        self.scale = scale;
+        self
+    }
+    /// Use the smallest and largest values as the domain.
+    /// Values the scale can't represent (like zero on a log scale) are ignored.
+    pub fn infer_domain(self, content: &[f32]) -> Self {
+        let usable = || content.iter().copied().filter(|v| self.scale.accepts(*v));
+        let min = usable().reduce(f32::min).unwrap_or(f32::MIN);
+        let mut max = usable().reduce(f32::max).unwrap_or(f32::MAX);
         if min == max {
             // Avoid division by zero
             max = min + 1.0;
@@ -58,6 +152,7 @@ impl Pipe {
             domain: min..=max,
             range: min..=max,
             overflow: Overflow::Extend,
+            scale: self.scale,
         }
     }
     pub fn set_domain(mut self, domain: &RangeInclusive<f32>) -> Self {
@@ -68,10 +163,25 @@ impl Pipe {
         self.range = range.clone();
         self
     }
+    pub fn domain(&self) -> &RangeInclusive<f32> {
+        &self.domain
+    }
+    pub fn range(&self) -> &RangeInclusive<f32> {
+        &self.range
+    }
+    /// The domain after it has been scaled, which is what actually gets mapped linearly
+    fn scaled_domain(&self) -> (f32, f32) {
+        (
+            self.scale.forward(*self.domain.start()),
+            self.scale.forward(*self.domain.end()),
+        )
+    }
     /// Apply this transformation to an f32
-    pub fn apply(&self, mut val: f32) -> f32 {
-        val -= self.domain.start();
-        val /= self.domain.end() - self.domain.start();
+    pub fn apply(&self, val: f32) -> f32 {
+        let (start, end) = self.scaled_domain();
+        let mut val = self.scale.forward(val);
+        val -= start;
+        val /= end - start;
         val *= self.range.end() - self.range.start();
         val += self.range.start();
         match self.overflow {
@@ -79,15 +189,151 @@ impl Pipe {
             Overflow::Extend => val,
         }
     }
+    /// Map a value from the display space back into the data space, the reverse of apply.
+    pub fn invert(&self, mut val: f32) -> f32 {
+        let (start, end) = self.scaled_domain();
+        val -= self.range.start();
+        val /= self.range.end() - self.range.start();
+        val *= end - start;
+        val += start;
+        let val = self.scale.backward(val);
+        match self.overflow {
+            Overflow::Saturate => {
+                let (low, high) = (self.domain.start(), self.domain.end());
+                val.clamp(low.min(*high), low.max(*high))
+            }
+            Overflow::Extend => val,
+        }
+    }
+    /// Pick about `count` round values within the domain, for labeling axes.
+    /// Steps are 1, 2 or 5 times a power of ten; log scales put ticks on powers of the base instead.
+    /// Use apply() to find where each tick belongs in the display space.
+    pub fn ticks(&self, count: usize) -> Vec<f32> {
+        let (low, high) = (self.domain.start(), self.domain.end());
+        let (low, high) = (low.min(*high), low.max(*high));
+        if let Scale::Log(base) = self.scale {
+            let (first, last) = (low.log(base).ceil(), high.log(base).floor());
+            if first.is_finite() && last - first >= 1.0 {
+                // Too many decades to label them all, so skip some
+                let stride = ((last - first + 1.0) / count.max(1) as f32).ceil().max(1.0) as usize;
+                return (first as i32..=last as i32)
+                    .step_by(stride)
+                    .map(|exponent| base.powi(exponent))
+                    .collect();
+            }
+            // Less than a decade in view, so plain ticks are more useful
+        }
+        linear_ticks(low, high, count)
+    }
     /// Bundle this pipe with a vector
     pub fn bundle(self, content: Vec<f32>) -> Feature {
         Feature {
             content,
             pipe: self,
+            axis: None,
+            frames: vec![],
         }
     }
 }
 
+/// A scale for categories, giving each distinct key an evenly spaced slot in the range.
+/// Keys sit in the middle of their slots, so nothing lands on the very edge of the Theater.
+#[derive(Debug, Clone)]
+pub struct Band {
+    keys: Vec<String>,
+    range: RangeInclusive<f32>,
+}
+
+/// Create a band from every distinct value, in the order they first appear
+impl<S: AsRef<str>> From<&[S]> for Band {
+    fn from(content: &[S]) -> Self {
+        Band::new(content.iter().map(|key| key.as_ref().to_string()))
+    }
+}
+
+impl Band {
+    pub fn new(keys: impl IntoIterator<Item = String>) -> Self {
+        let mut distinct: Vec<String> = vec![];
+        for key in keys {
+            if !distinct.contains(&key) {
+                distinct.push(key);
+            }
+        }
+        Band {
+            keys: distinct,
+            range: 0.0..=1.0,
+        }
+    }
+    pub fn fit_to(mut self, range: &RangeInclusive<f32>) -> Self {
+        self.range = range.clone();
+        self
+    }
+    pub fn keys(&self) -> &[String] {
+        &self.keys
+    }
+    /// Which slot this key occupies, if it is part of the band
+    pub fn index(&self, key: &str) -> Option<usize> {
+        self.keys.iter().position(|k| k == key)
+    }
+    /// The width of each slot in the display space
+    pub fn bandwidth(&self) -> f32 {
+        (self.range.end() - self.range.start()) / self.keys.len().max(1) as f32
+    }
+    /// A numeric pipe taking each key's index to the middle of its slot
+    pub fn pipe(&self) -> Pipe {
+        let slots = self.keys.len().max(1) as f32;
+        Pipe::new(-0.5..=slots - 0.5, self.range.clone())
+    }
+    /// Find the middle of this key's slot
+    pub fn apply(&self, key: &str) -> Option<f32> {
+        self.index(key).map(|i| self.pipe().apply(i as f32))
+    }
+    /// Bundle this band with some keys, so it can be used anywhere a Feature is.
+    /// Keys that aren't part of the band become NaN.
+    pub fn bundle<S: AsRef<str>>(&self, content: &[S]) -> Feature {
+        let content = content
+            .iter()
+            .map(|key| self.index(key.as_ref()).map_or(f32::NAN, |i| i as f32))
+            .collect();
+        self.pipe().bundle(content)
+    }
+    /// Color each key from a palette, cycling through the palette if there are more keys than colors.
+    /// Keys that aren't part of the band are gray.
+    pub fn colors<S: AsRef<str>>(&self, content: &[S], palette: &[Color]) -> Vec<Color> {
+        content
+            .iter()
+            .map(|key| match self.index(key.as_ref()) {
+                Some(i) if !palette.is_empty() => palette[i % palette.len()],
+                _ => Color::GRAY,
+            })
+            .collect()
+    }
+}
+
+/// Round ticks between low and high (inclusive), about count of them, spaced by 1, 2 or 5 * 10^n
+fn linear_ticks(low: f32, high: f32, count: usize) -> Vec<f32> {
+    if !(low.is_finite() && high.is_finite()) || count == 0 {
+        return vec![];
+    }
+    if low == high {
+        return vec![low];
+    }
+    let rough_step = (high - low) / count as f32;
+    let magnitude = 10f32.powf(rough_step.log10().floor());
+    // These thresholds are the geometric means between the candidate steps
+    let step = magnitude
+        * match rough_step / magnitude {
+            e if e >= 50f32.sqrt() => 10.0,
+            e if e >= 10f32.sqrt() => 5.0,
+            e if e >= 2f32.sqrt() => 2.0,
+            _ => 1.0,
+        };
+    // Count in whole steps so that error doesn't accumulate
+    let first = (low / step).ceil() as i64;
+    let last = (high / step).floor() as i64;
+    (first..=last).map(|i| i as f32 * step).collect()
+}
+
 #[test]
 fn test_pipe_scale() {
     let p = Pipe::from(&[0.0, 1.0, 2.0][..]).fit_to(&(-2.0..=0.0));
@@ -115,3 +361,110 @@ fn test_pipe_overflow() {
     assert_eq!(p.apply(1.0), 0.0);
     assert_eq!(p.apply(2.0), 1.0);
 }
+
+#[test]
+fn test_pipe_log_scale() {
+    let p = Pipe::new(1.0..=1000.0, 0.0..=3.0).scale(Scale::Log(10.0));
+    assert!((p.apply(1.0) - 0.0).abs() < 1e-5);
+    assert!((p.apply(10.0) - 1.0).abs() < 1e-5);
+    assert!((p.apply(100.0) - 2.0).abs() < 1e-5);
+    assert!((p.invert(2.0) - 100.0).abs() < 1e-2);
+
+    // Zero has no place on a log scale, so it's left out of the inferred domain
+    let p = Pipe::new(0.0..=1.0, 0.0..=1.0)
+        .scale(Scale::Log(10.0))
+        .infer_domain(&[0.0, 10.0, 1000.0])
+        .fit_to(&(0.0..=1.0));
+    assert!((p.apply(10.0) - 0.0).abs() < 1e-5);
+    assert!((p.apply(1000.0) - 1.0).abs() < 1e-5);
+}
+
+#[test]
+fn test_pipe_pow_and_symlog_scales() {
+    let p = Pipe::new(0.0..=100.0, 0.0..=1.0).scale(Scale::Pow(0.5));
+    assert!((p.apply(25.0) - 0.5).abs() < 1e-5);
+    assert!((p.invert(0.5) - 25.0).abs() < 1e-3);
+    // Negative values keep their sign
+    assert!((p.apply(-25.0) + 0.5).abs() < 1e-5);
+
+    let p = Pipe::new(-100.0..=100.0, -1.0..=1.0).scale(Scale::Symlog(1.0));
+    assert_eq!(p.apply(0.0), 0.0);
+    assert!((p.apply(100.0) - 1.0).abs() < 1e-5);
+    assert!((p.apply(-100.0) + 1.0).abs() < 1e-5);
+    for v in [-50.0, -1.0, 0.5, 7.0, 99.0] {
+        assert!((p.invert(p.apply(v)) - v).abs() < 1e-3, "symlog roundtrip of {}", v);
+    }
+}
+
+#[test]
+fn test_pipe_invert() {
+    let p = Pipe::from(&[0.0, 1.0, 2.0][..]).fit_to(&(-2.0..=0.0));
+    assert_eq!(p.invert(-2.0), 0.0);
+    assert_eq!(p.invert(-1.0), 1.0);
+    assert_eq!(p.invert(1.0), 3.0);
+
+    // Saturate clamps to the domain on the way back, too
+    let p = p.overflow(Overflow::Saturate);
+    assert_eq!(p.invert(1.0), 2.0);
+}
+
+#[test]
+fn test_pipe_ticks() {
+    let close = |a: Vec<f32>, b: &[f32]| {
+        a.len() == b.len() && a.iter().zip(b).all(|(x, y)| (x - y).abs() < 1e-4)
+    };
+    let p = Pipe::new(0.0..=10.0, -1.0..=1.0);
+    assert!(close(p.ticks(5), &[0.0, 2.0, 4.0, 6.0, 8.0, 10.0]));
+    assert!(close(p.ticks(10), &[0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0, 10.0]));
+    assert!(close(p.ticks(2), &[0.0, 5.0, 10.0]));
+
+    // Reversed domains, like latitude running north to south, still tick low to high
+    let p = Pipe::new(71.0..=25.0, -2.0..=2.0);
+    assert!(close(p.ticks(4), &[30.0, 40.0, 50.0, 60.0, 70.0]));
+
+    // Small, negative ranges
+    let p = Pipe::new(-0.33..=0.07, 0.0..=1.0);
+    assert!(close(p.ticks(4), &[-0.3, -0.2, -0.1, 0.0]));
+
+    // Log scales tick on powers of the base
+    let p = Pipe::new(3.0..=4_000_000.0, 0.0..=1.0).scale(Scale::Log(10.0));
+    assert!(close(p.ticks(10), &[10.0, 100.0, 1e3, 1e4, 1e5, 1e6]));
+    assert!(close(p.ticks(3), &[10.0, 1e3, 1e5]));
+}
+
+#[test]
+fn test_band() {
+    let states = ["Ohio", "Utah", "Ohio", "Iowa"];
+    let band = Band::from(&states[..]).fit_to(&(0.0..=3.0));
+    assert_eq!(band.keys(), &["Ohio", "Utah", "Iowa"]);
+    assert_eq!(band.bandwidth(), 1.0);
+    assert_eq!(band.apply("Ohio"), Some(0.5));
+    assert_eq!(band.apply("Iowa"), Some(2.5));
+    assert_eq!(band.apply("Texas"), None);
+
+    let feature = band.bundle(&["Utah", "Ohio", "Texas"]);
+    let converted = feature.convert();
+    assert_eq!(converted[..2], [1.5, 0.5]);
+    assert!(converted[2].is_nan());
+
+    let colors = band.colors(&states, &[Color::RED, Color::BLUE]);
+    assert_eq!(colors, vec![Color::RED, Color::BLUE, Color::RED, Color::RED]);
+}
+
+#[test]
+fn test_feature_frames() {
+    let feature = Pipe::new(0.0..=10.0, 0.0..=1.0).bundle(vec![]).frames(vec![
+        vec![0.0, 4.0, f32::NAN],
+        vec![10.0, 4.0, 2.0],
+        vec![5.0, 0.0, 2.0],
+    ]);
+    assert!(feature.is_animated());
+    assert_eq!(feature.convert()[..2], [0.0, 0.4]);
+    assert_eq!(feature.content_at(0.5)[..2], [5.0, 4.0]);
+    assert_eq!(feature.convert_at(1.5)[..2], [0.75, 0.2]);
+    // Missing values appear when the next frame is nearer
+    assert!(feature.content_at(0.25)[2].is_nan());
+    assert_eq!(feature.content_at(0.75)[2], 2.0);
+    // Past either end, it holds still
+    assert_eq!(feature.content_at(7.0), vec![5.0, 0.0, 2.0]);
+}
@@ -0,0 +1,310 @@
+use std::collections::HashMap;
+
+use bevy::{
+    core_pipeline::Transparent3d,
+    ecs::system::{lifetimeless::*, SystemParamItem},
+    pbr::{MeshPipeline, MeshPipelineKey, MeshUniform, SetMeshBindGroup, SetMeshViewBindGroup},
+    prelude::*,
+    render::{
+        mesh::{GpuBufferInfo, MeshVertexBufferLayout},
+        render_asset::RenderAssets,
+        render_phase::{
+            AddRenderCommand, DrawFunctions, EntityRenderCommand, RenderCommandResult, RenderPhase,
+            SetItemPipeline, TrackedRenderPass,
+        },
+        render_resource::*,
+        renderer::{RenderDevice, RenderQueue},
+        view::ExtractedView,
+        RenderApp, RenderStage,
+    },
+};
+use bytemuck::{Pod, Zeroable};
+
+/// Where to draw one copy of a mesh, and in what color
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+#[repr(C)]
+pub struct InstanceData {
+    pub position: Vec3,
+    /// How much to scale the mesh across its x and z
+    pub scale: f32,
+    pub color: [f32; 4],
+    /// Which way to turn the mesh's y axis, and how much to stretch it that way
+    pub up: Vec3,
+}
+impl InstanceData {
+    /// Copy a transform, as long as it scales x and z alike
+    pub fn new(transform: Transform, color: Color) -> Self {
+        InstanceData {
+            position: transform.translation,
+            scale: transform.scale.x,
+            color: color.as_rgba_f32(),
+            up: transform.rotation * Vec3::Y * transform.scale.y,
+        }
+    }
+
+    /// The same instance, scaled down to nothing
+    pub fn shrunk(&self) -> Self {
+        InstanceData {
+            scale: 0.0,
+            up: Vec3::ZERO,
+            ..*self
+        }
+    }
+
+    /// Part of the way from this instance to another
+    pub fn lerp(&self, to: &InstanceData, t: f32) -> Self {
+        let color = Vec4::from(self.color).lerp(Vec4::from(to.color), t);
+        InstanceData {
+            position: self.position.lerp(to.position, t),
+            scale: self.scale + (to.scale - self.scale) * t,
+            color: color.into(),
+            up: self.up.lerp(to.up, t),
+        }
+    }
+}
+
+/// Draw the entity's mesh once for each instance, all in one draw call.
+///
+/// The instances are positioned relative to the entity's transform. Bevy's frustum culling only
+/// knows about the mesh at the entity's own position, so add NoFrustumCulling as well.
+#[derive(Component, Debug, Clone, Default)]
+pub struct InstanceMaterialData(pub Vec<InstanceData>);
+
+/// Marks an entity in the render world as drawn with instances, whether or not they changed
+#[derive(Component)]
+struct Instanced;
+
+/// Renders InstanceMaterialData, for scenes with far too many objects to give each one an entity
+pub struct InstancingPlugin;
+impl Plugin for InstancingPlugin {
+    fn build(&self, app: &mut App) {
+        // Nothing to do without a renderer, e.g. in tests
+        if let Ok(render_app) = app.get_sub_app_mut(RenderApp) {
+            render_app
+                .add_render_command::<Transparent3d, DrawInstanced>()
+                .init_resource::<InstancedPipeline>()
+                .init_resource::<InstanceBuffers>()
+                .init_resource::<SpecializedMeshPipelines<InstancedPipeline>>()
+                .add_system_to_stage(RenderStage::Extract, extract_instances)
+                .add_system_to_stage(RenderStage::Queue, queue_instanced)
+                .add_system_to_stage(RenderStage::Prepare, prepare_instance_buffers);
+        }
+    }
+}
+
+/// Copy instances over to the render world only when they've changed, since there can be so many
+fn extract_instances(
+    mut commands: Commands,
+    query: Query<(Entity, &InstanceMaterialData, ChangeTrackers<InstanceMaterialData>)>,
+) {
+    for (entity, instances, tracker) in query.iter() {
+        let mut extracted = commands.get_or_spawn(entity);
+        extracted.insert(Instanced);
+        if tracker.is_changed() {
+            extracted.insert(instances.clone());
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn queue_instanced(
+    transparent_3d_draw_functions: Res<DrawFunctions<Transparent3d>>,
+    instanced_pipeline: Res<InstancedPipeline>,
+    msaa: Res<Msaa>,
+    mut pipelines: ResMut<SpecializedMeshPipelines<InstancedPipeline>>,
+    mut pipeline_cache: ResMut<PipelineCache>,
+    meshes: Res<RenderAssets<Mesh>>,
+    material_meshes: Query<(Entity, &MeshUniform, &Handle<Mesh>), With<Instanced>>,
+    mut views: Query<(&ExtractedView, &mut RenderPhase<Transparent3d>)>,
+) {
+    let draw_instanced = transparent_3d_draw_functions
+        .read()
+        .get_id::<DrawInstanced>()
+        .unwrap();
+    let msaa_key = MeshPipelineKey::from_msaa_samples(msaa.samples);
+
+    for (view, mut transparent_phase) in views.iter_mut() {
+        let view_row_2 = view.transform.compute_matrix().row(2);
+        for (entity, mesh_uniform, mesh_handle) in material_meshes.iter() {
+            if let Some(mesh) = meshes.get(mesh_handle) {
+                let key = msaa_key | MeshPipelineKey::from_primitive_topology(mesh.primitive_topology);
+                let pipeline = match pipelines.specialize(&mut pipeline_cache, &instanced_pipeline, key, &mesh.layout) {
+                    Ok(pipeline) => pipeline,
+                    Err(err) => {
+                        error!("Can't draw instances of this mesh: {}", err);
+                        continue;
+                    }
+                };
+                transparent_phase.add(Transparent3d {
+                    entity,
+                    pipeline,
+                    draw_function: draw_instanced,
+                    distance: view_row_2.dot(mesh_uniform.transform.col(3)),
+                });
+            }
+        }
+    }
+}
+
+/// The instances of one entity, uploaded to the GPU
+pub struct InstanceBuffer {
+    buffer: Buffer,
+    /// How many instances fit in the buffer
+    capacity: usize,
+    /// How many instances are in it now
+    length: usize,
+}
+
+/// The instances of every entity, kept on the GPU from frame to frame
+#[derive(Default)]
+pub struct InstanceBuffers(HashMap<Entity, InstanceBuffer>);
+
+/// Upload instances that changed, into the buffer they already have if they still fit
+fn prepare_instance_buffers(
+    mut buffers: ResMut<InstanceBuffers>,
+    changed: Query<(Entity, &InstanceMaterialData)>,
+    instanced: Query<(), With<Instanced>>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+) {
+    // Entities that are gone, or aren't drawn with instances anymore, don't need their buffers
+    buffers.0.retain(|entity, _| instanced.get(*entity).is_ok());
+    for (entity, instance_data) in changed.iter() {
+        let length = instance_data.0.len();
+        let contents = bytemuck::cast_slice(instance_data.0.as_slice());
+        match buffers.0.get_mut(&entity) {
+            Some(instances) if instances.capacity >= length => {
+                render_queue.write_buffer(&instances.buffer, 0, contents);
+                instances.length = length;
+            }
+            _ => {
+                let buffer = render_device.create_buffer_with_data(&BufferInitDescriptor {
+                    label: Some("instance data buffer"),
+                    contents,
+                    usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+                });
+                let instances = InstanceBuffer {
+                    buffer,
+                    capacity: length,
+                    length,
+                };
+                buffers.0.insert(entity, instances);
+            }
+        }
+    }
+}
+
+/// Bevy's mesh pipeline, with the instances as a second vertex buffer
+pub struct InstancedPipeline {
+    shader: Handle<Shader>,
+    mesh_pipeline: MeshPipeline,
+}
+
+impl FromWorld for InstancedPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let world = world.cell();
+        let asset_server = world.get_resource::<AssetServer>().unwrap();
+        let shader = asset_server.load("shaders/instancing.wgsl");
+        let mesh_pipeline = world.get_resource::<MeshPipeline>().unwrap();
+        InstancedPipeline {
+            shader,
+            mesh_pipeline: mesh_pipeline.clone(),
+        }
+    }
+}
+
+impl SpecializedMeshPipeline for InstancedPipeline {
+    type Key = MeshPipelineKey;
+
+    fn specialize(
+        &self,
+        key: Self::Key,
+        layout: &MeshVertexBufferLayout,
+    ) -> Result<RenderPipelineDescriptor, SpecializedMeshPipelineError> {
+        let mut descriptor = self.mesh_pipeline.specialize(key, layout)?;
+        descriptor.vertex.shader = self.shader.clone();
+        descriptor.vertex.buffers.push(VertexBufferLayout {
+            array_stride: std::mem::size_of::<InstanceData>() as u64,
+            step_mode: VertexStepMode::Instance,
+            attributes: vec![
+                // Locations 0-2 are the mesh's position, normal and UV
+                VertexAttribute {
+                    format: VertexFormat::Float32x4,
+                    offset: 0,
+                    shader_location: 3,
+                },
+                VertexAttribute {
+                    format: VertexFormat::Float32x4,
+                    offset: VertexFormat::Float32x4.size(),
+                    shader_location: 4,
+                },
+                VertexAttribute {
+                    format: VertexFormat::Float32x3,
+                    offset: VertexFormat::Float32x4.size() * 2,
+                    shader_location: 5,
+                },
+            ],
+        });
+        descriptor.fragment.as_mut().unwrap().shader = self.shader.clone();
+        descriptor.layout = Some(vec![
+            self.mesh_pipeline.view_layout.clone(),
+            self.mesh_pipeline.mesh_layout.clone(),
+        ]);
+        Ok(descriptor)
+    }
+}
+
+type DrawInstanced = (
+    SetItemPipeline,
+    SetMeshViewBindGroup<0>,
+    SetMeshBindGroup<1>,
+    DrawMeshInstanced,
+);
+
+/// Draw a mesh once for every instance in its InstanceBuffer
+pub struct DrawMeshInstanced;
+impl EntityRenderCommand for DrawMeshInstanced {
+    type Param = (
+        SRes<RenderAssets<Mesh>>,
+        SQuery<Read<Handle<Mesh>>>,
+        SRes<InstanceBuffers>,
+    );
+    #[inline]
+    fn render<'w>(
+        _view: Entity,
+        item: Entity,
+        (meshes, mesh_query, instance_buffers): SystemParamItem<'w, '_, Self::Param>,
+        pass: &mut TrackedRenderPass<'w>,
+    ) -> RenderCommandResult {
+        let (mesh_handle, instance_buffer) = match (mesh_query.get(item), instance_buffers.into_inner().0.get(&item)) {
+            (Ok(mesh_handle), Some(instance_buffer)) => (mesh_handle, instance_buffer),
+            _ => return RenderCommandResult::Failure,
+        };
+        let gpu_mesh = match meshes.into_inner().get(mesh_handle) {
+            Some(gpu_mesh) => gpu_mesh,
+            None => return RenderCommandResult::Failure,
+        };
+
+        pass.set_vertex_buffer(0, gpu_mesh.vertex_buffer.slice(..));
+        if instance_buffer.length == 0 {
+            return RenderCommandResult::Success;
+        }
+        // Only the front of the buffer is in use when the instances shrink
+        let used = (instance_buffer.length * std::mem::size_of::<InstanceData>()) as u64;
+        pass.set_vertex_buffer(1, instance_buffer.buffer.slice(..used));
+        match &gpu_mesh.buffer_info {
+            GpuBufferInfo::Indexed {
+                buffer,
+                index_format,
+                count,
+            } => {
+                pass.set_index_buffer(buffer.slice(..), 0, *index_format);
+                pass.draw_indexed(0..*count, 0, 0..instance_buffer.length as u32);
+            }
+            GpuBufferInfo::NonIndexed { vertex_count } => {
+                pass.draw(0..*vertex_count, 0..instance_buffer.length as u32);
+            }
+        }
+        RenderCommandResult::Success
+    }
+}
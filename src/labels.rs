@@ -0,0 +1,177 @@
+use bevy::prelude::*;
+use bevy_text_mesh::prelude::*;
+use itertools::Itertools;
+
+/// Rotate this entity to always point to the camera
+#[derive(Component)]
+pub struct RotateLock;
+
+/// Text in the Theater about something nearby, hidden when it would cover a more important label
+#[derive(Component)]
+pub struct Label {
+    /// The point the label is centered on
+    pub anchor: Vec3,
+    /// Roughly how wide and tall the text is, in the Theater's units
+    pub size: Vec2,
+    /// Labels with higher priority win when they overlap, from 0 to 1
+    pub priority: f32,
+}
+impl Label {
+    /// A priority from how big something is, as a share of the biggest of its kind. Regions
+    /// measured by area and points measured by size then compete on the same scale.
+    pub fn priority(value: f32, biggest: f32) -> f32 {
+        match value / biggest {
+            share if share.is_finite() => share.clamp(0.0, 1.0),
+            _ => 0.0,
+        }
+    }
+
+    /// Put some text over a point, with letters this tall
+    pub fn spawn(
+        commands: &mut Commands,
+        asset_server: &AssetServer,
+        text: &str,
+        anchor: Vec3,
+        height: f32,
+        priority: f32,
+    ) -> Entity {
+        // Text meshes can't be measured until they're built, so guess from an average letter
+        let size = Vec2::new(text.chars().count() as f32 * height * 0.55, height);
+        commands
+            .spawn_bundle(TextMeshBundle {
+                text_mesh: TextMesh {
+                    text: text.into(),
+                    style: TextMeshStyle {
+                        font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                        // Non-standard sizes are in hundredths of the Theater's units
+                        font_size: SizeUnit::NonStandard(height * 100.0),
+                        color: Color::WHITE,
+                        ..Default::default()
+                    },
+                    size: TextMeshSize {
+                        wrapping: false,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+                transform: Transform::from_translation(anchor),
+                ..Default::default()
+            })
+            .insert(Label {
+                anchor,
+                size,
+                priority,
+            })
+            .insert(RotateLock)
+            .id()
+    }
+}
+
+/// Billboard text toward the camera, and keep labels from piling on top of each other
+pub struct LabelPlugin;
+impl Plugin for LabelPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugin(TextMeshPlugin)
+            .add_system(lock_rotations)
+            .add_system(center_labels.after(lock_rotations))
+            .add_system(hide_overlapping_labels);
+    }
+}
+
+/// Keep the legend pointing at the camera all the time
+pub fn lock_rotations(
+    mut transform_pair: ParamSet<(
+        Query<&Transform, With<PerspectiveProjection>>,
+        Query<&mut Transform, With<RotateLock>>,
+    )>,
+) {
+    let camera_translation = match transform_pair.p0().get_single() {
+        Ok(camera) => camera.translation,
+        Err(_) => return,
+    };
+    for mut locked_transform in transform_pair.p1().iter_mut() {
+        // Text faces its back (+Z), so look straight away from the camera
+        let away = locked_transform.translation * 2.0 - camera_translation;
+        *locked_transform = locked_transform.looking_at(away, Vec3::Y);
+    }
+}
+
+/// Text starts at its transform, so slide each label left to center it on its anchor
+fn center_labels(mut labels: Query<(&mut Transform, &Label)>) {
+    for (mut transform, label) in labels.iter_mut() {
+        let left = transform.rotation * Vec3::X * label.size.x / 2.0;
+        let down = transform.rotation * Vec3::Y * label.size.y / 2.0;
+        transform.translation = label.anchor - left - down;
+    }
+}
+
+/// Hide labels that would overlap a higher priority label on screen
+fn hide_overlapping_labels(
+    windows: Res<Windows>,
+    images: Res<Assets<Image>>,
+    mut last_view: Local<Option<Mat4>>,
+    camera: Query<(&Camera, &GlobalTransform), With<PerspectiveProjection>>,
+    changed: Query<(), Changed<Label>>,
+    mut labels: Query<(&Label, &mut Visibility)>,
+) {
+    let (camera, camera_transform) = match camera.get_single() {
+        Ok(camera) => camera,
+        Err(_) => return,
+    };
+    // The window, or the image if it's rendering offscreen
+    let window = match camera.target.get_logical_size(&windows, &images) {
+        Some(size) => size,
+        None => return,
+    };
+    // Only recheck when the camera moves or labels are added or moved
+    let view = camera_transform.compute_matrix();
+    if *last_view == Some(view) && changed.is_empty() {
+        return;
+    }
+    *last_view = Some(view);
+    let world_to_ndc = camera.projection_matrix * view.inverse();
+    let (right, up) = (camera_transform.right(), camera_transform.up());
+    let screen_box = |label: &Label| {
+        let corners = [(-1.0, -1.0), (1.0, -1.0), (-1.0, 1.0), (1.0, 1.0)].map(|(x, y)| {
+            let corner = label.anchor + right * x * label.size.x / 2.0 + up * y * label.size.y / 2.0;
+            let clip = world_to_ndc * corner.extend(1.0);
+            // Behind the camera
+            (clip.w > 0.0).then(|| (clip.truncate().truncate() / clip.w + Vec2::ONE) / 2.0 * window)
+        });
+        let corners = corners.into_iter().collect::<Option<Vec<_>>>()?;
+        let low = corners.iter().fold(Vec2::splat(f32::INFINITY), |a, b| a.min(*b));
+        let high = corners.iter().fold(Vec2::splat(f32::NEG_INFINITY), |a, b| a.max(*b));
+        Some((low, high))
+    };
+
+    let mut shown: Vec<(Vec2, Vec2)> = vec![];
+    for (label, mut visibility) in labels
+        .iter_mut()
+        .sorted_by(|a, b| b.0.priority.total_cmp(&a.0.priority))
+    {
+        let visible = match screen_box(label) {
+            Some((low, high)) => {
+                let on_screen = high.x >= 0.0 && high.y >= 0.0 && low.x <= window.x && low.y <= window.y;
+                let clear = !shown
+                    .iter()
+                    .any(|(l, h)| low.x < h.x && l.x < high.x && low.y < h.y && l.y < high.y);
+                if on_screen && clear {
+                    shown.push((low, high));
+                }
+                on_screen && clear
+            }
+            None => false,
+        };
+        if visibility.is_visible != visible {
+            visibility.is_visible = visible;
+        }
+    }
+}
+
+#[test]
+fn test_label_priority() {
+    // A big state and a big point are on the same footing, whatever their units
+    assert_eq!(Label::priority(600.0, 600.0), Label::priority(0.3, 0.3));
+    assert_eq!(Label::priority(150.0, 600.0), 0.25);
+    assert_eq!(Label::priority(1.0, 0.0), 0.0);
+}
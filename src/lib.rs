@@ -1,9 +1,21 @@
+pub mod capture;
+pub mod clock;
 pub mod errors;
 pub mod feature;
+pub mod instancing;
+pub mod labels;
 pub mod meshutil;
+pub mod overlay;
+pub mod palette;
 pub mod people;
+pub mod picking;
+pub mod projection;
 pub mod scatterplot;
+pub mod simplify;
+pub mod stream;
+pub mod table;
 pub mod theater;
+pub mod timeline;
 pub mod usmap;
 pub mod util;
 pub mod visuals;
@@ -1,25 +1,247 @@
 use std::path::PathBuf;
 
+use avis::capture::Capture;
 use avis::errors::Result;
+use avis::projection::Projection;
+use avis::table::Columns;
+use avis::usmap::GeoLayer;
+use avis::visuals::reliefmap::MapSettings;
+use avis::visuals::scatter::ScatterSettings;
 use clap::arg;
 
+/// Names of the glyphs points can be drawn as
+const GLYPHS: [&str; 5] = ["sphere", "cube", "cone", "column", "disc"];
+
+/// Let a command write images instead of opening a window
+fn with_capture(command: clap::Command) -> clap::Command {
+    command
+        .arg(arg!(--output <PNG> "Write an image and exit, without opening a window").required(false))
+        .arg(
+            arg!(--frames <COUNT> "How many frames to write, numbered after the output")
+                .required(false)
+                .default_value("1"),
+        )
+        .arg(
+            arg!(--resolution <PIXELS> "Width and height of the images")
+                .required(false)
+                .default_value("1280x720"),
+        )
+        .arg(
+            arg!(--turntable <SECONDS> "Circle the camera for some seconds, writing every frame")
+                .required(false)
+                .requires("output"),
+        )
+        .arg(
+            arg!(--"orbit-radius" <DISTANCE> "How far out to circle, instead of far enough to see the whole stage")
+                .required(false)
+                .requires("turntable"),
+        )
+        .arg(
+            arg!(--"orbit-height" <DISTANCE> "How high above the middle of the stage to circle")
+                .required(false)
+                .requires("turntable"),
+        )
+        .arg(
+            arg!(--fps <RATE> "Frames per second of the clip, which paces anything that's animated")
+                .required(false)
+                .default_value("30"),
+        )
+}
+
+/// The Capture a command asks for, if it has an output
+fn capture(subargs: &clap::ArgMatches) -> Result<Option<Capture>> {
+    let output = match subargs.value_of("output") {
+        Some(output) => output,
+        None => return Ok(None),
+    };
+    let size = subargs.value_of("resolution").unwrap_or_default();
+    let (width, height) = size
+        .split_once('x')
+        .and_then(|(width, height)| Some((width.parse().ok()?, height.parse().ok()?)))
+        .ok_or_else(|| anyhow::anyhow!("Resolutions look like 1280x720, not {}", size))?;
+    let fps: f32 = subargs.value_of_t_or_exit("fps");
+    if !(fps.is_finite() && fps > 0.0) {
+        anyhow::bail!("The frame rate has to be more than 0, not {}", fps);
+    }
+    let mut capture = Capture::new(output);
+    capture.frames = subargs.value_of_t_or_exit("frames");
+    capture.size = bevy::math::UVec2::new(width, height);
+    capture.fps = fps;
+    if subargs.is_present("turntable") {
+        let seconds: f32 = subargs.value_of_t_or_exit("turntable");
+        if !(seconds.is_finite() && seconds > 0.0) {
+            anyhow::bail!("A turntable has to last more than 0 seconds, not {}", seconds);
+        }
+        capture = capture.turntable(seconds, fps);
+        if let Some(orbit) = capture.orbit.as_mut() {
+            orbit.radius = subargs.is_present("orbit-radius").then(|| subargs.value_of_t_or_exit("orbit-radius"));
+            orbit.height = subargs.is_present("orbit-height").then(|| subargs.value_of_t_or_exit("orbit-height"));
+        }
+    }
+    Ok(Some(capture))
+}
+
 fn main() -> Result<()> {
-    let wordcloudcommand = clap::Command::new("wordcloud")
-        .arg(arg!(--words <WORDLIST> "JSON file containing list of words to use, see example"));
-    let mapcommand = clap::Command::new("map");
+    let wordcloudcommand = with_capture(clap::Command::new("wordcloud"))
+        .arg(arg!(--words <WORDLIST> "JSON file containing list of words to use, see example"))
+        .arg(arg!(--stream <SOURCE> "Read more rows while running, one JSON object per line: -, unix:PATH, or tcp:PORT").required(false));
+    let mapcommand = with_capture(clap::Command::new("map"))
+        .arg(
+            arg!(--values <VALUES> "JSON object of values keyed by region name or id, for a choropleth, optionally keyed by date first")
+                .required(false),
+        )
+        .arg(arg!(--extrude "Raise each region by its value").requires("choropleth"))
+        .arg(arg!(--globe "Wrap the map and points around a globe"))
+        .arg(arg!(--labels "Write the names of the regions and cities"))
+        .arg(arg!(--instanced "Draw all the points at once, which is faster but can't be picked"))
+        .arg(
+            arg!(--glyph <GLYPH> "Shape of the points, where columns make bars as tall as the altitude")
+                .required(false)
+                .possible_values(GLYPHS)
+                .default_value("sphere"),
+        )
+        .arg(arg!(--"glyph-by" <COLUMN> "Column of glyph names or categories to shape points by").required(false))
+        .arg(
+            arg!(--"border-width" <WIDTH> "Width of the borders between regions, or 0 for none")
+                .required(false)
+                .default_value("0.01"),
+        )
+        .arg(
+            arg!(--projection <PROJECTION> "How to flatten the globe")
+                .required(false)
+                .possible_values(["albers-usa", "albers", "mercator", "equirectangular"])
+                .default_value("albers-usa"),
+        )
+        .arg(arg!(--layer <GEOJSON> "GeoJSON file of regions to draw, instead of the US states").required(false))
+        .arg(arg!(--"name-key" <KEY> "Property holding each region's name").required(false))
+        .arg(arg!(--"id-key" <KEY> "Property holding each region's id, like a FIPS code").required(false))
+        .arg(arg!(--"value-key" <KEY> "Numeric property of each region to use for the choropleth").required(false))
+        .group(clap::ArgGroup::new("choropleth").args(&["values", "value-key"]).multiple(true))
+        .arg(
+            arg!(--points <TABLE> "CSV, JSON lines, or JSON table of points to show over the map")
+                .required(false)
+                .default_value("points.json"),
+        )
+        .arg(arg!(--columns <MAPPING> "JSON object choosing which columns to use for what").required(false))
+        .arg(arg!(--lat <COLUMN> "Column of latitudes").required(false))
+        .arg(arg!(--lon <COLUMN> "Column of longitudes").required(false))
+        .arg(arg!(--alt <COLUMN> "Column of heights above the map").required(false))
+        .arg(arg!(--size <COLUMN> "Column of point sizes").required(false))
+        .arg(arg!(--color <COLUMN> "Column of numbers or categories to color points by").required(false))
+        .arg(arg!(--label <COLUMN> "Column of point names").required(false))
+        .arg(arg!(--time <COLUMN> "Column of dates, to animate points listed once per date").required(false))
+        .arg(arg!(--stream <SOURCE> "Read more rows while running, one JSON object per line: -, unix:PATH, or tcp:PORT").required(false));
+    let scattercommand = with_capture(clap::Command::new("scatter"))
+        .arg(arg!(--points <TABLE> "CSV, JSON lines, or JSON table of points").required_unless_present("stream"))
+        .arg(arg!(--x <COLUMN> "Column spread across the width").required(false).default_value("x"))
+        .arg(arg!(--y <COLUMN> "Column spread up the height").required(false).default_value("y"))
+        .arg(arg!(--z <COLUMN> "Column spread through the depth").required(false).default_value("z"))
+        .arg(arg!(--size <COLUMN> "Column of point sizes").required(false))
+        .arg(arg!(--color <COLUMN> "Column of numbers or categories to color points by").required(false))
+        .arg(arg!(--label <COLUMN> "Column of point names").required(false).default_value("name"))
+        .arg(arg!(--labels "Write each point's name next to it"))
+        .arg(arg!(--instanced "Draw all the points at once, which is faster but can't be picked"))
+        .arg(
+            arg!(--glyph <GLYPH> "Shape of the points, where columns rise from the floor")
+                .required(false)
+                .possible_values(GLYPHS)
+                .default_value("sphere"),
+        )
+        .arg(arg!(--"glyph-by" <COLUMN> "Column of glyph names or categories to shape points by").required(false))
+        .arg(arg!(--time <COLUMN> "Column of dates, to animate points listed once per date").required(false))
+        .arg(arg!(--stream <SOURCE> "Read more rows while running, one JSON object per line: -, unix:PATH, or tcp:PORT").required(false));
     let args = clap::Command::new("avis")
         .subcommand(wordcloudcommand)
         .subcommand(mapcommand)
+        .subcommand(scattercommand)
         .get_matches();
     match args.subcommand() {
         Some(("wordcloud", subargs)) => {
-            avis::visuals::wordcloud::WordCloudVisual::new(
+            let mut cloud = avis::visuals::wordcloud::WordCloudVisual::new(
                 &subargs.value_of_t_or_exit::<PathBuf>("words"),
-            )?
-            .start()?;
+            )?;
+            if subargs.is_present("stream") {
+                cloud = cloud.stream(subargs.value_of_t_or_exit("stream"));
+            }
+            if let Some(capture) = capture(subargs)? {
+                cloud = cloud.capture(capture);
+            }
+            cloud.start()?;
+        }
+        Some(("map", subargs)) => {
+            let mut layer = match subargs.value_of("layer") {
+                Some(path) => GeoLayer::new(path),
+                None => GeoLayer::default(),
+            };
+            if let Some(key) = subargs.value_of("name-key") {
+                layer = layer.name_key(key);
+            }
+            if let Some(key) = subargs.value_of("id-key") {
+                layer = layer.id_key(key);
+            }
+            let projection = match subargs.value_of("projection") {
+                Some("albers") => Projection::albers_conus(),
+                Some("mercator") => Projection::Mercator,
+                Some("equirectangular") => Projection::Equirectangular,
+                _ => Projection::AlbersUsa,
+            };
+            let mut columns = match subargs.value_of("columns") {
+                Some(path) => Columns::read(path.as_ref())?,
+                None => Columns::default(),
+            };
+            if let Some(column) = subargs.value_of("lat") {
+                columns.lat = column.into();
+            }
+            if let Some(column) = subargs.value_of("lon") {
+                columns.lon = column.into();
+            }
+            for (role, column) in [
+                ("alt", &mut columns.alt),
+                ("size", &mut columns.size),
+                ("color", &mut columns.color),
+                ("label", &mut columns.label),
+                ("glyph-by", &mut columns.glyph),
+                ("time", &mut columns.time),
+            ] {
+                if let Some(name) = subargs.value_of(role) {
+                    *column = Some(name.into());
+                }
+            }
+            avis::visuals::reliefmap::main(MapSettings {
+                projection,
+                layer,
+                values: subargs.value_of("values").map(PathBuf::from),
+                value_key: subargs.value_of("value-key").map(String::from),
+                extrude: subargs.is_present("extrude"),
+                globe: subargs.is_present("globe"),
+                border_width: subargs.value_of_t_or_exit("border-width"),
+                labels: subargs.is_present("labels"),
+                points: subargs.value_of_t_or_exit("points"),
+                columns,
+                instanced: subargs.is_present("instanced"),
+                glyph: subargs.value_of_t_or_exit("glyph"),
+                stream: subargs.is_present("stream").then(|| subargs.value_of_t_or_exit("stream")),
+                capture: capture(subargs)?,
+            })?;
         }
-        Some(("map", _subargs)) => {
-            avis::visuals::reliefmap::main()?;
+        Some(("scatter", subargs)) => {
+            let column = |name: &str| subargs.value_of(name).map(String::from);
+            avis::visuals::scatter::main(ScatterSettings {
+                points: subargs.value_of("points").map(PathBuf::from),
+                x: subargs.value_of_t_or_exit("x"),
+                y: subargs.value_of_t_or_exit("y"),
+                z: subargs.value_of_t_or_exit("z"),
+                size: column("size"),
+                color: column("color"),
+                label: column("label"),
+                labels: subargs.is_present("labels"),
+                instanced: subargs.is_present("instanced"),
+                glyph: subargs.value_of_t_or_exit("glyph"),
+                glyph_by: column("glyph-by"),
+                time: column("time"),
+                stream: subargs.is_present("stream").then(|| subargs.value_of_t_or_exit("stream")),
+                capture: capture(subargs)?,
+            })?;
         }
         _ => panic!("Please choose a command"),
     }
@@ -1,4 +1,6 @@
 use bevy::prelude::*;
+use bevy::render::mesh::Indices;
+use bevy::render::render_resource::PrimitiveTopology;
 
 use crate::errors::*;
 
@@ -29,3 +31,324 @@ pub fn estimate_vertex_normals(positions: &[Vec3], indices: &[u32]) -> Result<Ve
 
     Ok(vertex_normals)
 }
+
+/// The signed area of a ring of points: positive when they run counter-clockwise
+pub fn signed_area(ring: &[Vec2]) -> f32 {
+    let mut area = 0.0;
+    for (i, a) in ring.iter().enumerate() {
+        let b = ring[(i + 1) % ring.len()];
+        area += a.x * b.y - b.x * a.y;
+    }
+    area / 2.0
+}
+
+/// Accumulates triangles with flat shading, giving each face its own vertices
+#[derive(Default)]
+struct FlatMesh {
+    positions: Vec<[f32; 3]>,
+    normals: Vec<[f32; 3]>,
+    uvs: Vec<[f32; 2]>,
+    indices: Vec<u32>,
+}
+impl FlatMesh {
+    /// Add a triangle, wound so that it faces toward `outward`
+    fn triangle(&mut self, mut corners: [(Vec3, Vec2); 3], outward: Vec3) {
+        let normal = (corners[1].0 - corners[0].0).cross(corners[2].0 - corners[0].0);
+        if normal.dot(outward) < 0.0 {
+            corners.swap(1, 2);
+        }
+        let normal = normal.normalize_or_zero();
+        let normal = if normal.dot(outward) < 0.0 { -normal } else { normal };
+        for (position, uv) in corners {
+            self.indices.push(self.positions.len() as u32);
+            self.positions.push(position.to_array());
+            self.normals.push(normal.to_array());
+            self.uvs.push(uv.to_array());
+        }
+    }
+    fn into_mesh(self) -> Mesh {
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, self.positions);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, self.normals);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, self.uvs);
+        mesh.set_indices(Some(Indices::U32(self.indices)));
+        mesh
+    }
+}
+
+/// Raise a flat polygon into a closed solid, with a top and bottom cap joined by walls.
+///
+/// The first ring is the outline and any others are holes. The triangles index into the points of
+/// all the rings, taken in order, and cover the polygon. `place` puts a flat point at a height in the
+/// world, so the same solid can stand on a plane or on a globe. UVs are the flat coordinates.
+/// If the bottom and top are the same, you get a single flat sheet instead.
+pub fn extrude_polygon(
+    rings: &[Vec<Vec2>],
+    triangles: &[[usize; 3]],
+    bottom: f32,
+    top: f32,
+    place: impl Fn(Vec2, f32) -> Vec3,
+) -> Mesh {
+    let mut mesh = FlatMesh::default();
+    let points = rings.iter().flatten().copied().collect::<Vec<_>>();
+    // Which way is up, at a spot on the polygon
+    let up = |p: Vec2| place(p, top + 1.0) - place(p, top);
+
+    for triangle in triangles {
+        let flat = triangle.map(|i| points[i]);
+        let middle = (flat[0] + flat[1] + flat[2]) / 3.0;
+        mesh.triangle(flat.map(|p| (place(p, top), p)), up(middle));
+        if bottom != top {
+            mesh.triangle(flat.map(|p| (place(p, bottom), p)), -up(middle));
+        }
+    }
+
+    if bottom != top {
+        for (ring_index, ring) in rings.iter().enumerate() {
+            // Walls face away from the inside of the polygon, which is to the right of a
+            // counter-clockwise outline but to the left of a counter-clockwise hole
+            let is_hole = ring_index > 0;
+            let outward_is_right = (signed_area(ring) > 0.0) != is_hole;
+            for (i, &a) in ring.iter().enumerate() {
+                let b = ring[(i + 1) % ring.len()];
+                let along = b - a;
+                if along.length_squared() == 0.0 {
+                    continue;
+                }
+                let right = Vec2::new(along.y, -along.x);
+                let outward_flat = if outward_is_right { right } else { -right };
+                let middle = (a + b) / 2.0;
+                let outward = place(middle + outward_flat * 0.01, bottom) - place(middle, bottom);
+                let corners = [
+                    (place(a, bottom), a),
+                    (place(b, bottom), b),
+                    (place(b, top), b),
+                    (place(a, top), a),
+                ];
+                mesh.triangle([corners[0], corners[1], corners[2]], outward);
+                mesh.triangle([corners[0], corners[2], corners[3]], outward);
+            }
+        }
+    }
+    mesh.into_mesh()
+}
+
+/// Trace the rings of a flat polygon with ribbons of constant width, lying flat at a height.
+///
+/// `place` works as in extrude_polygon, so outlines follow the same plane or globe as the fill.
+/// Corners are mitered, but never pushed out further than twice the width.
+/// UVs run along each ribbon in x, and across it from 0 to 1 in y.
+pub fn outline_rings(
+    rings: &[Vec<Vec2>],
+    height: f32,
+    width: f32,
+    place: impl Fn(Vec2, f32) -> Vec3,
+) -> Mesh {
+    let mut positions = vec![];
+    let mut normals = vec![];
+    let mut uvs = vec![];
+    let mut indices = vec![];
+    for ring in rings {
+        let mut ring = ring.clone();
+        ring.dedup();
+        if ring.len() > 1 && ring.first() == ring.last() {
+            ring.pop();
+        }
+        if ring.len() < 2 {
+            continue;
+        }
+        let n = ring.len();
+        let start = positions.len() as u32;
+        let mut along = 0.0;
+        for i in 0..n {
+            let point = place(ring[i], height);
+            let up = (place(ring[i], height + 1.0) - point).normalize_or_zero();
+            let before = point - place(ring[(i + n - 1) % n], height);
+            let after = place(ring[(i + 1) % n], height) - point;
+            let side_before = before.cross(up).normalize_or_zero();
+            let side_after = after.cross(up).normalize_or_zero();
+            let miter = (side_before + side_after).normalize_or_zero();
+            let miter = if miter == Vec3::ZERO { side_after } else { miter };
+            let stretch = 1.0 / miter.dot(side_after).max(0.5);
+            let offset = miter * width / 2.0 * stretch;
+            for (position, across) in [(point + offset, 0.0), (point - offset, 1.0)] {
+                positions.push(position.to_array());
+                normals.push(up.to_array());
+                uvs.push([along, across]);
+            }
+            along += after.length();
+        }
+        for i in 0..n as u32 {
+            let j = (i + 1) % n as u32;
+            let [a, b, c, d] = [2 * i, 2 * i + 1, 2 * j, 2 * j + 1].map(|k| start + k);
+            indices.extend([a, c, d, a, d, b]);
+        }
+    }
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+    mesh.set_indices(Some(Indices::U32(indices)));
+    mesh
+}
+
+/// A solid of revolution around the y axis with flat caps: a cylinder, a cone, or anything between.
+///
+/// The radius runs from `bottom_radius` at y = `bottom` to `top_radius` at y = `top`, and a radius of 0
+/// closes that end to a point. The sides are smooth, using `segments` faces around.
+/// UVs wrap around the sides in x and run up them in y; on the caps they're a circle in the unit square.
+pub fn frustum(bottom_radius: f32, top_radius: f32, bottom: f32, top: f32, segments: usize) -> Mesh {
+    let mut positions = vec![];
+    let mut normals = vec![];
+    let mut uvs = vec![];
+    let mut indices = vec![];
+    let around = |i: usize| {
+        let angle = i as f32 / segments as f32 * std::f32::consts::TAU;
+        Vec3::new(angle.cos(), 0.0, angle.sin())
+    };
+
+    // The sides lean in as much as the radius shrinks
+    for i in 0..=segments {
+        let out = around(i);
+        let normal = (out * (top - bottom) + Vec3::Y * (bottom_radius - top_radius)).normalize_or_zero();
+        let u = i as f32 / segments as f32;
+        for (radius, y, v) in [(bottom_radius, bottom, 0.0), (top_radius, top, 1.0)] {
+            positions.push((out * radius + Vec3::Y * y).to_array());
+            normals.push(normal.to_array());
+            uvs.push([u, v]);
+        }
+    }
+    for i in 0..segments as u32 {
+        let [b0, t0, b1, t1] = [2 * i, 2 * i + 1, 2 * i + 2, 2 * i + 3];
+        // Leave out the triangles that would collapse into the point of a cone
+        if bottom_radius > 0.0 {
+            indices.extend([b0, t0, b1]);
+        }
+        if top_radius > 0.0 {
+            indices.extend([b1, t0, t1]);
+        }
+    }
+
+    for (radius, y, up) in [(bottom_radius, bottom, -1.0), (top_radius, top, 1.0)] {
+        if radius <= 0.0 {
+            continue;
+        }
+        let center = positions.len() as u32;
+        positions.push([0.0, y, 0.0]);
+        normals.push([0.0, up, 0.0]);
+        uvs.push([0.5, 0.5]);
+        for i in 0..segments {
+            let out = around(i);
+            positions.push((out * radius + Vec3::Y * y).to_array());
+            normals.push([0.0, up, 0.0]);
+            uvs.push([0.5 + out.x / 2.0, 0.5 + out.z / 2.0]);
+        }
+        for i in 0..segments as u32 {
+            let (a, b) = (center + 1 + i, center + 1 + (i + 1) % segments as u32);
+            // Counter-clockwise as seen from outside, which flips between the top and bottom
+            indices.extend(if up > 0.0 { [center, b, a] } else { [center, a, b] });
+        }
+    }
+
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+    mesh.set_indices(Some(Indices::U32(indices)));
+    mesh
+}
+
+#[test]
+fn test_extrude_square() {
+    use bevy::render::mesh::VertexAttributeValues;
+    // A unit square with the corners listed clockwise, raised by 2
+    let square = vec![
+        Vec2::new(0.0, 0.0),
+        Vec2::new(0.0, 1.0),
+        Vec2::new(1.0, 1.0),
+        Vec2::new(1.0, 0.0),
+    ];
+    let mesh = extrude_polygon(&[square], &[[0, 1, 2], [0, 2, 3]], 0.0, 2.0, |p, h| {
+        Vec3::new(p.x, h, p.y)
+    });
+    let positions = match mesh.attribute(Mesh::ATTRIBUTE_POSITION) {
+        Some(VertexAttributeValues::Float32x3(positions)) => positions.clone(),
+        _ => panic!("Extruded mesh has no positions"),
+    };
+    let normals = match mesh.attribute(Mesh::ATTRIBUTE_NORMAL) {
+        Some(VertexAttributeValues::Float32x3(normals)) => normals.clone(),
+        _ => panic!("Extruded mesh has no normals"),
+    };
+    // Two triangles each for two caps and four walls
+    assert_eq!(positions.len(), 12 * 3);
+    let center = Vec3::new(0.5, 1.0, 0.5);
+    for face in positions.chunks(3).zip(normals.chunks(3)) {
+        let corners = face.0.iter().map(|p| Vec3::from(*p)).collect::<Vec<_>>();
+        let normal = Vec3::from(face.1[0]);
+        // Every face points away from the middle of the box
+        let middle = (corners[0] + corners[1] + corners[2]) / 3.0;
+        assert!(normal.dot(middle - center) > 0.0, "Face {:?} points inward", corners);
+        // ..and is wound counter-clockwise when seen from outside
+        let winding = (corners[1] - corners[0]).cross(corners[2] - corners[0]);
+        assert!(winding.dot(normal) > 0.0, "Face {:?} is wound backward", corners);
+    }
+}
+
+#[test]
+fn test_outline_square() {
+    use bevy::render::mesh::VertexAttributeValues;
+    let square = vec![
+        Vec2::new(0.0, 0.0),
+        Vec2::new(1.0, 0.0),
+        Vec2::new(1.0, 1.0),
+        Vec2::new(0.0, 1.0),
+        Vec2::new(0.0, 0.0),
+    ];
+    let mesh = outline_rings(std::slice::from_ref(&square), 0.5, 0.2, |p, h| Vec3::new(p.x, h, p.y));
+    let positions = match mesh.attribute(Mesh::ATTRIBUTE_POSITION) {
+        Some(VertexAttributeValues::Float32x3(positions)) => positions.clone(),
+        _ => panic!("Outline mesh has no positions"),
+    };
+    // Two vertices per corner, with the closing point dropped, and two triangles per side
+    assert_eq!(positions.len(), 8);
+    assert_eq!(mesh.indices().map(|i| i.len()), Some(4 * 6));
+    for (corner, pair) in square.iter().zip(positions.chunks(2)) {
+        let corner = Vec3::new(corner.x, 0.5, corner.y);
+        let (a, b) = (Vec3::from(pair[0]), Vec3::from(pair[1]));
+        // Mitered corners sit diagonally on either side of the ring, a half width from each edge
+        assert!((a - corner + (b - corner)).length() < 1e-5);
+        assert!(((a - corner).abs() - Vec3::new(0.1, 0.0, 0.1)).length() < 1e-5);
+    }
+}
+
+#[test]
+fn test_frustum() {
+    use bevy::render::mesh::VertexAttributeValues;
+    for (bottom_radius, top_radius) in [(1.0, 1.0), (1.0, 0.0), (0.5, 2.0)] {
+        let mesh = frustum(bottom_radius, top_radius, -1.0, 1.0, 12);
+        let (positions, normals) = match (
+            mesh.attribute(Mesh::ATTRIBUTE_POSITION),
+            mesh.attribute(Mesh::ATTRIBUTE_NORMAL),
+        ) {
+            (Some(VertexAttributeValues::Float32x3(p)), Some(VertexAttributeValues::Float32x3(n))) => (p, n),
+            _ => panic!("Frustum has no positions or normals"),
+        };
+        let indices = match mesh.indices() {
+            Some(Indices::U32(indices)) => indices,
+            _ => panic!("Frustum has no indices"),
+        };
+        // Sides, plus a cap for each end that isn't a point
+        let caps = [bottom_radius, top_radius].iter().filter(|r| **r > 0.0).count();
+        assert_eq!(indices.len(), (12 * caps + 12 * caps) * 3);
+        for face in indices.chunks(3) {
+            let corners = face.iter().map(|&i| Vec3::from(positions[i as usize])).collect::<Vec<_>>();
+            let middle = (corners[0] + corners[1] + corners[2]) / 3.0;
+            let winding = (corners[1] - corners[0]).cross(corners[2] - corners[0]);
+            assert!(winding.dot(middle) > 0.0, "Face {:?} is wound inward", corners);
+            for &i in face {
+                let normal = Vec3::from(normals[i as usize]);
+                assert!(normal.dot(winding) > 0.0, "Face {:?} has a normal facing in", corners);
+            }
+        }
+    }
+}
@@ -0,0 +1,28 @@
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+
+/// The font for text drawn over the Theater, like tooltips and dates
+pub const OVERLAY_FONT: HandleUntyped = HandleUntyped::weak_from_u64(Font::TYPE_UUID, 0x2f6e_8a31_9c4b_0d57);
+
+/// Text drawn flat over the Theater, for the tooltip, the date, and the like.
+/// Every plugin with something to show adds it, but it's only set up once.
+pub struct OverlayPlugin;
+impl Plugin for OverlayPlugin {
+    fn build(&self, app: &mut App) {
+        if app.world.contains_resource::<Overlay>() {
+            return;
+        }
+        app.insert_resource(Overlay).add_startup_system(setup_overlay);
+    }
+}
+
+/// Marks that the overlay has been set up
+struct Overlay;
+
+fn setup_overlay(mut commands: Commands, mut fonts: ResMut<Assets<Font>>) {
+    commands.spawn_bundle(UiCameraBundle::default());
+    // bevy_text_mesh claims .ttf files for its own fonts, so this one skips the asset server
+    let font = Font::try_from_bytes(include_bytes!("../assets/fonts/FiraSans-Bold.ttf").to_vec())
+        .expect("The overlay font is invalid");
+    fonts.set_untracked(OVERLAY_FONT, font);
+}
@@ -0,0 +1,173 @@
+use bevy::prelude::*;
+
+use crate::feature::{Feature, Overflow, Pipe};
+
+/// A gradient of colors spread over 0..=1, blended linearly between stops
+#[derive(Debug, Clone)]
+pub struct Palette {
+    stops: Vec<(f32, Color)>,
+}
+
+/// Turn 0xRRGGBB into a color, which keeps the palette tables readable
+fn hex(rgb: u32) -> Color {
+    Color::rgb_u8((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8)
+}
+
+/// Blend between two colors, from all `low` at 0 to all `high` at 1
+pub fn mix(low: Color, high: Color, t: f32) -> Color {
+    let low = Vec4::from(low.as_rgba_f32());
+    let high = Vec4::from(high.as_rgba_f32());
+    let [r, g, b, a] = low.lerp(high, t).to_array();
+    Color::rgba(r, g, b, a)
+}
+
+impl Palette {
+    /// Create a palette from stops at arbitrary positions within 0..=1
+    pub fn new(mut stops: Vec<(f32, Color)>) -> Self {
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Palette { stops }
+    }
+    /// Create a palette with the colors spaced evenly from 0 to 1
+    pub fn even(colors: &[Color]) -> Self {
+        let last = colors.len().saturating_sub(1).max(1) as f32;
+        Palette::new(
+            colors
+                .iter()
+                .enumerate()
+                .map(|(i, color)| (i as f32 / last, *color))
+                .collect(),
+        )
+    }
+    fn from_hex(colors: &[u32]) -> Self {
+        Palette::even(&colors.iter().copied().map(hex).collect::<Vec<_>>())
+    }
+    /// Sequential, dark purple to yellow, perceptually uniform
+    pub fn viridis() -> Self {
+        Palette::from_hex(&[
+            0x440154, 0x472c7a, 0x3b518b, 0x2c718e, 0x21908d, 0x27ad81, 0x5cc863, 0xaadc32, 0xfde725,
+        ])
+    }
+    /// Sequential, black through purple and orange to pale yellow
+    pub fn magma() -> Self {
+        Palette::from_hex(&[
+            0x000004, 0x1c1044, 0x4f127b, 0x812581, 0xb5367a, 0xe55964, 0xfb8761, 0xfec287, 0xfcfdbf,
+        ])
+    }
+    /// Sequential, navy to yellow, readable with most kinds of color blindness
+    pub fn cividis() -> Self {
+        Palette::from_hex(&[
+            0x00224e, 0x123570, 0x3b496c, 0x575d6d, 0x707173, 0x8a8779, 0xa69d75, 0xc4b56c, 0xfee838,
+        ])
+    }
+    /// Diverging, red through white to blue, for values on either side of a middle
+    pub fn red_blue() -> Self {
+        Palette::from_hex(&[
+            0xb2182b, 0xd6604d, 0xf4a582, 0xfddbc7, 0xf7f7f7, 0xd1e5f0, 0x92c5de, 0x4393c3, 0x2166ac,
+        ])
+    }
+    /// Ten distinct colors for categories, e.g. with Band::colors. Not meant to be blended.
+    pub fn category10() -> Vec<Color> {
+        [
+            0x1f77b4, 0xff7f0e, 0x2ca02c, 0xd62728, 0x9467bd, 0x8c564b, 0xe377c2, 0x7f7f7f, 0xbcbd22,
+            0x17becf,
+        ]
+        .into_iter()
+        .map(hex)
+        .collect()
+    }
+    /// Find the color at this position, from 0 to 1
+    pub fn sample(&self, position: f32) -> Color {
+        if position.is_nan() || self.stops.is_empty() {
+            return Color::GRAY;
+        }
+        // Find the first stop past this position, and blend it with the one before it
+        let after = self.stops.iter().position(|(at, _)| *at >= position);
+        let (low, high) = match after {
+            Some(0) => return self.stops[0].1,
+            None => return self.stops[self.stops.len() - 1].1,
+            Some(i) => (self.stops[i - 1], self.stops[i]),
+        };
+        mix(low.1, high.1, (position - low.0) / (high.0 - low.0))
+    }
+    /// Take some evenly spaced colors from the palette, such as one for each key of a Band
+    pub fn samples(&self, count: usize) -> Vec<Color> {
+        let last = count.saturating_sub(1).max(1) as f32;
+        (0..count).map(|i| self.sample(i as f32 / last)).collect()
+    }
+    /// Use this palette to show the output of a pipe
+    pub fn scale(self, pipe: Pipe) -> ColorScale {
+        ColorScale::new(pipe, self)
+    }
+}
+
+/// A transformation from an input space to colors, through a Pipe and then a Palette
+#[derive(Debug, Clone)]
+pub struct ColorScale {
+    pipe: Pipe,
+    palette: Palette,
+}
+impl ColorScale {
+    /// The pipe's domain is kept, but its range is always the whole palette
+    pub fn new(pipe: Pipe, palette: Palette) -> Self {
+        ColorScale {
+            pipe: pipe.fit_to(&(0.0..=1.0)).overflow(Overflow::Saturate),
+            palette,
+        }
+    }
+    /// Stretch the domain so that this value lands in the middle of the palette,
+    /// which is what diverging palettes like red_blue expect
+    pub fn centered(mut self, middle: f32) -> Self {
+        let (start, end) = (*self.pipe.domain().start(), *self.pipe.domain().end());
+        let reach = (start - middle).abs().max((end - middle).abs());
+        let domain = if start <= end {
+            middle - reach..=middle + reach
+        } else {
+            middle + reach..=middle - reach
+        };
+        self.pipe = self.pipe.set_domain(&domain);
+        self
+    }
+    /// Color one value. Missing values (NaN) are gray.
+    pub fn apply(&self, val: f32) -> Color {
+        self.palette.sample(self.pipe.apply(val))
+    }
+    /// Color every value
+    pub fn convert(&self, content: &[f32]) -> Vec<Color> {
+        content.iter().map(|v| self.apply(*v)).collect()
+    }
+    /// Color a Feature's original content, ignoring its own pipe
+    pub fn convert_feature(&self, feature: &Feature) -> Vec<Color> {
+        self.convert(&feature.content)
+    }
+}
+
+#[test]
+fn test_palette_sample() {
+    let palette = Palette::even(&[Color::BLACK, Color::WHITE]);
+    assert_eq!(palette.sample(0.0), Color::BLACK);
+    assert_eq!(palette.sample(1.0), Color::WHITE);
+    assert_eq!(palette.sample(-1.0), Color::BLACK);
+    assert_eq!(palette.sample(0.5), Color::rgba(0.5, 0.5, 0.5, 1.0));
+    assert_eq!(palette.sample(f32::NAN), Color::GRAY);
+    assert_eq!(palette.samples(3)[1], Color::rgba(0.5, 0.5, 0.5, 1.0));
+
+    // Custom stops don't need to be evenly spaced, or in order
+    let palette = Palette::new(vec![(1.0, Color::WHITE), (0.0, Color::BLACK), (0.9, Color::RED)]);
+    assert_eq!(palette.sample(0.9), Color::RED);
+    assert_eq!(palette.sample(0.45), Color::rgba(0.5, 0.0, 0.0, 1.0));
+}
+
+#[test]
+fn test_color_scale() {
+    let scale = Palette::even(&[Color::BLACK, Color::WHITE]).scale(Pipe::new(0.0..=10.0, -5.0..=5.0));
+    assert_eq!(scale.apply(0.0), Color::BLACK);
+    assert_eq!(scale.apply(20.0), Color::WHITE);
+
+    // Centering a lopsided domain stretches the short side
+    let scale = Palette::red_blue()
+        .scale(Pipe::new(-2.0..=10.0, 0.0..=1.0))
+        .centered(0.0);
+    assert_eq!(scale.apply(0.0), hex(0xf7f7f7));
+    assert_eq!(scale.apply(-10.0), hex(0xb2182b));
+    assert_eq!(scale.apply(10.0), hex(0x2166ac));
+}
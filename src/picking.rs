@@ -0,0 +1,327 @@
+use bevy::prelude::*;
+use bevy::render::mesh::{Indices, VertexAttributeValues};
+use bevy::render::primitives::Aabb;
+
+use crate::overlay::{OverlayPlugin, OVERLAY_FONT};
+
+/// What to say about an entity when the cursor is over it.
+/// Anything with a Tooltip and a mesh can be picked.
+#[derive(Component, Debug, Clone, Default, PartialEq)]
+pub struct Tooltip {
+    pub title: String,
+    /// Named values to list under the title
+    pub values: Vec<(String, String)>,
+}
+impl Tooltip {
+    pub fn new(title: impl Into<String>) -> Self {
+        Tooltip {
+            title: title.into(),
+            values: vec![],
+        }
+    }
+    /// Add a line to the tooltip
+    pub fn with(mut self, name: impl Into<String>, value: impl ToString) -> Self {
+        self.values.push((name.into(), value.to_string()));
+        self
+    }
+}
+impl std::fmt::Display for Tooltip {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.title)?;
+        for (name, value) in &self.values {
+            write!(f, "\n{}: {}", name, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// The entity under the cursor, if any
+#[derive(Default)]
+pub struct Hovered {
+    pub entity: Option<Entity>,
+    /// The entity that was lit up, so it can be put back
+    highlighted: Option<Entity>,
+    /// Where the last ray came from, so it's only cast again when something moves
+    last_cast: Option<(Vec2, Mat4)>,
+}
+
+/// Marks the text that follows the cursor
+#[derive(Component)]
+struct TooltipText;
+
+/// A half-line through the Theater, such as from the camera through the cursor
+#[derive(Debug, Clone, Copy)]
+pub struct Ray {
+    pub origin: Vec3,
+    /// Always normalized
+    pub direction: Vec3,
+}
+impl Ray {
+    pub fn new(origin: Vec3, direction: Vec3) -> Self {
+        Ray {
+            origin,
+            direction: direction.normalize(),
+        }
+    }
+
+    /// The ray from a camera through a point on its window, measured in pixels from the bottom left
+    pub fn from_screen(cursor: Vec2, window: Vec2, camera: &Camera, transform: &GlobalTransform) -> Self {
+        let ndc = cursor / window * 2.0 - Vec2::ONE;
+        let ndc_to_world = transform.compute_matrix() * camera.projection_matrix.inverse();
+        // Bevy's depth is reversed, so the near plane is at 1 and the distance is toward 0
+        let near = ndc_to_world.project_point3(ndc.extend(1.0));
+        let further = ndc_to_world.project_point3(ndc.extend(0.5));
+        Ray::new(near, further - near)
+    }
+
+    /// How far along the ray it meets the triangle ABC, from either side (Möller–Trumbore)
+    pub fn hit_triangle(&self, a: Vec3, b: Vec3, c: Vec3) -> Option<f32> {
+        let (ab, ac) = (b - a, c - a);
+        let p = self.direction.cross(ac);
+        let determinant = ab.dot(p);
+        if determinant.abs() < f32::EPSILON {
+            // The ray runs parallel to the triangle
+            return None;
+        }
+        let to_origin = self.origin - a;
+        let u = to_origin.dot(p) / determinant;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+        let q = to_origin.cross(ab);
+        let v = self.direction.dot(q) / determinant;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+        let distance = ac.dot(q) / determinant;
+        (distance >= 0.0).then_some(distance)
+    }
+
+    /// Whether the ray passes through an axis-aligned box, using the slab method
+    pub fn hits_box(&self, low: Vec3, high: Vec3) -> bool {
+        let inverse = self.direction.recip();
+        let (a, b) = ((low - self.origin) * inverse, (high - self.origin) * inverse);
+        let enter = a.min(b).max_element();
+        let exit = a.max(b).min_element();
+        // NaNs from a ray along the edge of a slab count as a hit
+        !(enter > exit || exit < 0.0)
+    }
+
+    /// How far along the ray it first meets a mesh, placed by a transform
+    pub fn hit_mesh(&self, mesh: &Mesh, transform: &GlobalTransform) -> Option<f32> {
+        // Work in the mesh's own space, then measure the distance back in the world
+        let to_local = transform.compute_matrix().inverse();
+        let local = Ray::new(
+            to_local.transform_point3(self.origin),
+            to_local.transform_vector3(self.direction),
+        );
+        let positions = match mesh.attribute(Mesh::ATTRIBUTE_POSITION) {
+            Some(VertexAttributeValues::Float32x3(positions)) => positions,
+            _ => return None,
+        };
+        let indices: Vec<usize> = match mesh.indices() {
+            Some(Indices::U32(indices)) => indices.iter().map(|&i| i as usize).collect(),
+            Some(Indices::U16(indices)) => indices.iter().map(|&i| i as usize).collect(),
+            None => (0..positions.len()).collect(),
+        };
+        let nearest = indices
+            .chunks_exact(3)
+            .filter_map(|t| {
+                let [a, b, c] = [t[0], t[1], t[2]].map(|i| Vec3::from(positions[i]));
+                local.hit_triangle(a, b, c)
+            })
+            .fold(None, |nearest: Option<f32>, d| Some(nearest.map_or(d, |n| n.min(d))))?;
+        let hit = transform.compute_matrix().transform_point3(local.origin + local.direction * nearest);
+        Some(hit.distance(self.origin))
+    }
+}
+
+/// Highlight whatever is under the cursor, and describe it in a tooltip
+pub struct PickingPlugin;
+impl Plugin for PickingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugin(OverlayPlugin)
+            .init_resource::<Hovered>()
+            .add_startup_system(setup_tooltip)
+            .add_system(pick_hovered)
+            .add_system(highlight_hovered.after(pick_hovered))
+            .add_system(show_tooltip.after(pick_hovered));
+    }
+}
+
+fn setup_tooltip(mut commands: Commands) {
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                ..Default::default()
+            },
+            text: Text::with_section(
+                "",
+                TextStyle {
+                    font: OVERLAY_FONT.typed(),
+                    font_size: 18.0,
+                    color: Color::WHITE,
+                },
+                Default::default(),
+            ),
+            visibility: Visibility { is_visible: false },
+            ..Default::default()
+        })
+        .insert(TooltipText);
+}
+
+/// What picking needs to know about anything with a Tooltip
+type Pickable<'a> = (
+    Entity,
+    &'a Handle<Mesh>,
+    &'a GlobalTransform,
+    Option<&'a Aabb>,
+    Option<&'a Visibility>,
+);
+
+/// Cast a ray from the cursor and find the nearest thing with a Tooltip
+fn pick_hovered(
+    windows: Res<Windows>,
+    meshes: Res<Assets<Mesh>>,
+    mut hovered: ResMut<Hovered>,
+    camera: Query<(&Camera, &GlobalTransform), With<PerspectiveProjection>>,
+    pickable: Query<Pickable, With<Tooltip>>,
+) {
+    let (window, (camera, camera_transform)) = match (windows.get_primary(), camera.get_single().ok()) {
+        (Some(window), Some(camera)) => (window, camera),
+        _ => return,
+    };
+    let cursor = match window.cursor_position() {
+        Some(cursor) => cursor,
+        None => {
+            if hovered.entity.is_some() {
+                hovered.entity = None;
+            }
+            return;
+        }
+    };
+    // Meshes are only searched when the cursor or the camera move
+    let cast = (cursor, camera_transform.compute_matrix());
+    if hovered.last_cast == Some(cast) {
+        return;
+    }
+    hovered.last_cast = Some(cast);
+
+    let window_size = Vec2::new(window.width(), window.height());
+    let ray = Ray::from_screen(cursor, window_size, camera, camera_transform);
+    let nearest = pickable
+        .iter()
+        .filter(|(.., visibility)| visibility.is_none_or(|v| v.is_visible))
+        .filter_map(|(entity, mesh, transform, aabb, _)| {
+            // Most things are nowhere near the cursor, so rule them out by their bounding boxes first
+            if let Some(aabb) = aabb {
+                let (low, high) = world_box(aabb, transform);
+                if !ray.hits_box(low, high) {
+                    return None;
+                }
+            }
+            let distance = ray.hit_mesh(meshes.get(mesh)?, transform)?;
+            Some((entity, distance))
+        })
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(entity, _)| entity);
+    if hovered.entity != nearest {
+        hovered.entity = nearest;
+    }
+}
+
+/// The corners of a box in the world that holds a transformed bounding box
+fn world_box(aabb: &Aabb, transform: &GlobalTransform) -> (Vec3, Vec3) {
+    let to_world = transform.compute_matrix();
+    let (center, half) = (Vec3::from(aabb.center), Vec3::from(aabb.half_extents));
+    (0..8)
+        .map(|corner| {
+            let sign = Vec3::new(
+                if corner & 1 == 0 { -1.0 } else { 1.0 },
+                if corner & 2 == 0 { -1.0 } else { 1.0 },
+                if corner & 4 == 0 { -1.0 } else { 1.0 },
+            );
+            to_world.transform_point3(center + half * sign)
+        })
+        .fold(
+            (Vec3::splat(f32::INFINITY), Vec3::splat(f32::NEG_INFINITY)),
+            |(low, high), p| (low.min(p), high.max(p)),
+        )
+}
+
+/// Light up the hovered entity, and dim the one before it
+fn highlight_hovered(
+    mut hovered: ResMut<Hovered>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    handles: Query<&Handle<StandardMaterial>>,
+) {
+    if hovered.highlighted == hovered.entity {
+        return;
+    }
+    let mut set_glow = |entity: Option<Entity>, glow: Color| {
+        if let Some(material) = entity
+            .and_then(|e| handles.get(e).ok())
+            .and_then(|handle| materials.get_mut(handle))
+        {
+            material.emissive = glow;
+        }
+    };
+    set_glow(hovered.highlighted, Color::BLACK);
+    set_glow(hovered.entity, Color::rgb(0.25, 0.25, 0.25));
+    hovered.highlighted = hovered.entity;
+}
+
+/// Describe the hovered entity next to the cursor
+fn show_tooltip(
+    windows: Res<Windows>,
+    hovered: Res<Hovered>,
+    tooltips: Query<&Tooltip>,
+    mut text: Query<(&mut Text, &mut Style, &mut Visibility), With<TooltipText>>,
+) {
+    let (mut text, mut style, mut visibility) = match text.get_single_mut() {
+        Ok(text) => text,
+        Err(_) => return,
+    };
+    let tooltip = hovered.entity.and_then(|e| tooltips.get(e).ok());
+    let cursor = windows.get_primary().and_then(|w| w.cursor_position());
+    match (tooltip, cursor) {
+        (Some(tooltip), Some(cursor)) => {
+            visibility.is_visible = true;
+            text.sections[0].value = tooltip.to_string();
+            // Sit just to the right of the cursor, hanging down from it
+            style.position = Rect {
+                left: Val::Px(cursor.x + 16.0),
+                bottom: Val::Px(cursor.y),
+                ..Default::default()
+            };
+        }
+        _ => visibility.is_visible = false,
+    }
+}
+
+#[test]
+fn test_ray_hits() {
+    let ray = Ray::new(Vec3::new(0.0, 5.0, 0.0), Vec3::new(0.0, -2.0, 0.0));
+    let (a, b, c) = (Vec3::new(-1.0, 0.0, -1.0), Vec3::new(1.0, 0.0, -1.0), Vec3::new(0.0, 0.0, 1.0));
+    assert_eq!(ray.hit_triangle(a, b, c), Some(5.0));
+    // Triangles are hit from either side, but not from behind the ray
+    assert_eq!(ray.hit_triangle(a, c, b), Some(5.0));
+    assert_eq!(Ray::new(ray.origin, Vec3::Y).hit_triangle(a, b, c), None);
+    let offset = Vec3::new(3.0, 0.0, 0.0);
+    assert_eq!(ray.hit_triangle(a + offset, b + offset, c + offset), None);
+
+    assert!(ray.hits_box(Vec3::splat(-1.0), Vec3::splat(1.0)));
+    assert!(!ray.hits_box(Vec3::new(2.0, -1.0, -1.0), Vec3::new(3.0, 1.0, 1.0)));
+    assert!(!ray.hits_box(Vec3::new(-1.0, 6.0, -1.0), Vec3::new(1.0, 7.0, 1.0)));
+
+    let mesh: Mesh = shape::Cube { size: 2.0 }.into();
+    let transform = GlobalTransform::from_translation(Vec3::new(0.0, 1.0, 0.0));
+    assert!((ray.hit_mesh(&mesh, &transform).unwrap() - 3.0).abs() < 1e-5);
+}
+
+#[test]
+fn test_tooltip_text() {
+    let tooltip = Tooltip::new("Ohio").with("id", "39").with("value", 1.5);
+    assert_eq!(tooltip.to_string(), "Ohio\nid: 39\nvalue: 1.5");
+}
@@ -0,0 +1,313 @@
+use std::f32::consts::FRAC_PI_4;
+use std::ops::RangeInclusive;
+
+use bevy::prelude::*;
+
+use crate::feature::{Feature, Pipe};
+
+/// A way to flatten longitude and latitude (in degrees) onto a plane.
+/// Projected coordinates are in radians of a unit sphere, with x to the east and y to the north.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Projection {
+    /// Longitude and latitude used directly as x and y
+    Equirectangular,
+    /// Conformal, and stretches toward the poles. Latitudes past 85 degrees can't be shown.
+    Mercator,
+    /// Albers equal-area conic, given two standard parallels and an origin (longitude, latitude)
+    Albers {
+        parallels: (f32, f32),
+        origin: (f32, f32),
+    },
+    /// Albers for the lower 48 states, with Alaska shrunk and Hawaii moved into insets to the southwest.
+    /// Places outside the US can't be shown.
+    AlbersUsa,
+}
+
+impl Projection {
+    /// Albers fit to the lower 48 states
+    pub fn albers_conus() -> Self {
+        Projection::Albers {
+            parallels: (29.5, 45.5),
+            origin: (-96.0, 37.5),
+        }
+    }
+
+    /// Project a point, or None if this projection can't show it
+    pub fn project(&self, lon: f32, lat: f32) -> Option<Vec2> {
+        if !(lon.is_finite() && lat.is_finite()) {
+            return None;
+        }
+        match *self {
+            Projection::Equirectangular => Some(Vec2::new(lon.to_radians(), lat.to_radians())),
+            Projection::Mercator => {
+                if lat.abs() > 85.0 {
+                    return None;
+                }
+                let y = (FRAC_PI_4 + lat.to_radians() / 2.0).tan().ln();
+                Some(Vec2::new(lon.to_radians(), y))
+            }
+            Projection::Albers { parallels, origin } => Some(albers(parallels, origin, lon, lat)),
+            Projection::AlbersUsa => {
+                // These insets match the usual layout (as in d3's geoAlbersUsa)
+                if (18.0..=23.0).contains(&lat) && (-161.0..=-154.0).contains(&lon) {
+                    let hawaii = albers((8.0, 18.0), (-157.0, 3.0), lon, lat);
+                    Some(hawaii + Vec2::new(-0.205, -0.212))
+                } else if (51.0..=72.0).contains(&lat) && (lon <= -129.0 || lon >= 170.0) {
+                    // The Aleutians cross the antimeridian, which albers() wraps for us
+                    let alaska = albers((55.0, 65.0), (-154.0, 50.0), lon, lat);
+                    Some(alaska * 0.35 + Vec2::new(-0.307, -0.201))
+                } else if (24.0..=50.0).contains(&lat) && (-125.0..=-66.0).contains(&lon) {
+                    Some(albers((29.5, 45.5), (-96.0, 37.5), lon, lat))
+                } else {
+                    None
+                }
+            }
+        }
+    }
+}
+
+/// Albers equal-area conic projection on a unit sphere
+fn albers(parallels: (f32, f32), origin: (f32, f32), lon: f32, lat: f32) -> Vec2 {
+    let (phi1, phi2) = (parallels.0.to_radians(), parallels.1.to_radians());
+    let n = (phi1.sin() + phi2.sin()) / 2.0;
+    let c = phi1.cos().powi(2) + 2.0 * n * phi1.sin();
+    let rho = |lat: f32| (c - 2.0 * n * lat.to_radians().sin()).max(0.0).sqrt() / n;
+    // Keep the longitude within half a turn of the origin, so nothing wraps the long way around
+    let lambda = (lon - origin.0 + 540.0).rem_euclid(360.0) - 180.0;
+    let theta = n * lambda.to_radians();
+    let r = rho(lat);
+    Vec2::new(r * theta.sin(), rho(origin.1) - r * theta.cos())
+}
+
+/// A projection fit into a rectangle of the Theater's floor, without stretching it.
+/// East is +x, and north is -z, so the map reads normally from the default camera.
+#[derive(Debug, Clone)]
+pub struct MapFrame {
+    projection: Projection,
+    x: Pipe,
+    z: Pipe,
+}
+
+impl MapFrame {
+    /// Fit everything the projection can show within a box of longitudes and latitudes
+    /// into a rectangle of the floor, centered, keeping its proportions
+    pub fn fit(
+        projection: Projection,
+        lons: RangeInclusive<f32>,
+        lats: RangeInclusive<f32>,
+        width: &RangeInclusive<f32>,
+        depth: &RangeInclusive<f32>,
+    ) -> Self {
+        // Conic projections curve the edges of the box, so look inside it, not just at the corners
+        let steps = 40;
+        let along = |range: &RangeInclusive<f32>, i: usize| {
+            range.start() + (range.end() - range.start()) * i as f32 / steps as f32
+        };
+        let (low, high) = (0..=steps)
+            .flat_map(|i| (0..=steps).map(move |j| (i, j)))
+            .filter_map(|(i, j)| projection.project(along(&lons, i), along(&lats, j)))
+            .fold(
+                (Vec2::splat(f32::INFINITY), Vec2::splat(f32::NEG_INFINITY)),
+                |(low, high), p| (low.min(p), high.max(p)),
+            );
+        let span = (high - low).max(Vec2::splat(f32::EPSILON));
+        let room = Vec2::new(width.end() - width.start(), depth.end() - depth.start());
+        let scale = (room.x / span.x).min(room.y / span.y);
+        let middle = Vec2::new(
+            (width.start() + width.end()) / 2.0,
+            (depth.start() + depth.end()) / 2.0,
+        );
+        let half = span * scale / 2.0;
+        MapFrame {
+            projection,
+            x: Pipe::new(low.x..=high.x, middle.x - half.x..=middle.x + half.x),
+            // North is the far side of the floor
+            z: Pipe::new(high.y..=low.y, middle.y - half.y..=middle.y + half.y),
+        }
+    }
+
+    pub fn projection(&self) -> Projection {
+        self.projection
+    }
+
+    /// Find where a place belongs on the floor, as (x, z), or None if it can't be shown
+    pub fn apply(&self, lon: f32, lat: f32) -> Option<Vec2> {
+        let p = self.projection.project(lon, lat)?;
+        Some(Vec2::new(self.x.apply(p.x), self.z.apply(p.y)))
+    }
+
+    /// Project many places into x and z Features, e.g. for a Scatterplot.
+    /// Places that can't be shown become NaN.
+    pub fn features(&self, lons: &[f32], lats: &[f32]) -> (Feature, Feature) {
+        let (x, z): (Vec<f32>, Vec<f32>) = lons
+            .iter()
+            .zip(lats)
+            .map(|(&lon, &lat)| match self.apply(lon, lat) {
+                Some(p) => (p.x, p.y),
+                None => (f32::NAN, f32::NAN),
+            })
+            .unzip();
+        // These are already in the Theater's space, so they pass through unchanged
+        let identity = Pipe::new(0.0..=1.0, 0.0..=1.0);
+        (identity.clone().bundle(x), identity.bundle(z))
+    }
+}
+
+/// Where geographic data is drawn: flat on the Theater's floor, or around a globe
+#[derive(Debug, Clone)]
+pub enum Surface {
+    Flat(MapFrame),
+    /// A sphere, turned so that the `facing` place (longitude, latitude) points toward +z.
+    /// Altitudes rise straight out from the surface.
+    Globe {
+        center: Vec3,
+        radius: f32,
+        facing: (f32, f32),
+    },
+}
+
+impl From<MapFrame> for Surface {
+    fn from(frame: MapFrame) -> Self {
+        Surface::Flat(frame)
+    }
+}
+
+impl Surface {
+    /// Find where a place belongs in the Theater, at some altitude above the surface,
+    /// or None if it can't be shown
+    pub fn place(&self, lon: f32, lat: f32, altitude: f32) -> Option<Vec3> {
+        match self {
+            Surface::Flat(frame) => frame.apply(lon, lat).map(|p| Vec3::new(p.x, altitude, p.y)),
+            Surface::Globe {
+                center,
+                radius,
+                facing,
+            } => {
+                if !(lon.is_finite() && lat.is_finite() && altitude.is_finite()) {
+                    return None;
+                }
+                let (lon, lat) = (lon.to_radians(), lat.to_radians());
+                let outward = Vec3::new(lat.cos() * lon.sin(), lat.sin(), lat.cos() * lon.cos());
+                // Spin the facing longitude around to the front, then tip the facing latitude down to it
+                let turn = Quat::from_rotation_x(facing.1.to_radians())
+                    * Quat::from_rotation_y(-facing.0.to_radians());
+                Some(*center + turn * outward * (*radius + altitude))
+            }
+        }
+    }
+
+    /// Place many points into x, y and z Features, e.g. for a Scatterplot.
+    /// Altitudes are already in the Theater's units. Places that can't be shown become NaN.
+    pub fn features(&self, lons: &[f32], lats: &[f32], alts: &[f32]) -> (Feature, Feature, Feature) {
+        let mut axes = [vec![], vec![], vec![]];
+        for ((&lon, &lat), &alt) in lons.iter().zip(lats).zip(alts) {
+            let p = self.place(lon, lat, alt).unwrap_or(Vec3::splat(f32::NAN));
+            for (axis, value) in axes.iter_mut().zip(p.to_array()) {
+                axis.push(value);
+            }
+        }
+        // These are already in the Theater's space, so they pass through unchanged
+        let identity = Pipe::new(0.0..=1.0, 0.0..=1.0);
+        let [x, y, z] = axes;
+        (
+            identity.clone().bundle(x),
+            identity.clone().bundle(y),
+            identity.bundle(z),
+        )
+    }
+}
+
+#[test]
+fn test_simple_projections() {
+    let p = Projection::Equirectangular.project(90.0, -45.0).unwrap();
+    assert!((p - Vec2::new(std::f32::consts::FRAC_PI_2, -FRAC_PI_4)).length() < 1e-4);
+
+    let mercator = Projection::Mercator;
+    assert!(mercator.project(10.0, 0.0).unwrap().y.abs() < 1e-6);
+    // Mercator stretches latitudes apart toward the poles
+    let y = |lat| mercator.project(0.0, lat).unwrap().y;
+    assert!(y(60.0) - y(50.0) > y(20.0) - y(10.0));
+    assert_eq!(mercator.project(0.0, 89.0), None);
+}
+
+#[test]
+fn test_albers_is_equal_area() {
+    let albers = Projection::albers_conus();
+    assert!(albers.project(-96.0, 37.5).unwrap().length() < 1e-6);
+    // A one degree cell has a true area proportional to the cosine of its latitude
+    let area = |lon: f32, lat: f32| {
+        let a = albers.project(lon, lat).unwrap();
+        let b = albers.project(lon + 1.0, lat).unwrap();
+        let c = albers.project(lon, lat + 1.0).unwrap();
+        (b - a).perp_dot(c - a).abs()
+    };
+    let expected = (46.5f32.to_radians().cos()) / (25.5f32.to_radians().cos());
+    let ratio = area(-70.0, 46.0) / area(-110.0, 25.0);
+    assert!((ratio - expected).abs() < 0.01, "{} vs {}", ratio, expected);
+}
+
+#[test]
+fn test_albers_usa_insets() {
+    let usa = Projection::AlbersUsa;
+    let seattle = usa.project(-122.3, 47.6).unwrap();
+    let miami = usa.project(-80.2, 25.8).unwrap();
+    let anchorage = usa.project(-149.9, 61.2).unwrap();
+    let honolulu = usa.project(-157.9, 21.3).unwrap();
+    let aleutians = usa.project(178.0, 52.0).unwrap();
+    // Both insets sit below the lower 48, with Alaska on the far left
+    for inset in [anchorage, honolulu, aleutians] {
+        assert!(inset.y < seattle.y && inset.x < miami.x);
+    }
+    assert!(aleutians.x < anchorage.x && anchorage.x < honolulu.x);
+    // Elsewhere can't be shown
+    assert_eq!(usa.project(2.35, 48.86), None);
+}
+
+#[test]
+fn test_map_frame_fit() {
+    let frame = MapFrame::fit(
+        Projection::Equirectangular,
+        -10.0..=10.0,
+        0.0..=10.0,
+        &(-5.0..=5.0),
+        &(-5.0..=5.0),
+    );
+    // Twice as wide as it is tall, so it fills the width and half the depth
+    let northwest = frame.apply(-10.0, 10.0).unwrap();
+    let southeast = frame.apply(10.0, 0.0).unwrap();
+    assert!((northwest - Vec2::new(-5.0, -2.5)).length() < 1e-4);
+    assert!((southeast - Vec2::new(5.0, 2.5)).length() < 1e-4);
+
+    let (x, z) = frame.features(&[0.0, f32::NAN], &[5.0, 5.0]);
+    assert!(x.convert()[0].abs() < 1e-4 && z.convert()[0].abs() < 1e-4);
+    assert!(x.convert()[1].is_nan());
+}
+
+#[test]
+fn test_globe_surface() {
+    let globe = Surface::Globe {
+        center: Vec3::new(0.0, 2.0, 0.0),
+        radius: 2.0,
+        facing: (-98.0, 39.0),
+    };
+    let close = |a: Vec3, b: Vec3| (a - b).length() < 1e-4;
+    // The facing place is right in front, and altitude pushes straight out
+    assert!(close(globe.place(-98.0, 39.0, 0.0).unwrap(), Vec3::new(0.0, 2.0, 2.0)));
+    assert!(close(globe.place(-98.0, 39.0, 1.0).unwrap(), Vec3::new(0.0, 2.0, 3.0)));
+    // North is up, and east is to the right
+    let north = globe.place(-98.0, 49.0, 0.0).unwrap();
+    let east = globe.place(-88.0, 39.0, 0.0).unwrap();
+    assert!(north.y > 2.0 && north.x.abs() < 1e-4);
+    assert!(east.x > 0.0);
+    for (lon, lat) in [(0.0, 0.0), (179.0, -80.0), (-45.0, 89.0)] {
+        let p = globe.place(lon, lat, 0.5).unwrap();
+        assert!(((p - Vec3::new(0.0, 2.0, 0.0)).length() - 2.5).abs() < 1e-4);
+    }
+
+    let (x, y, z) = globe.features(&[-98.0, f32::NAN], &[39.0, 0.0], &[0.0, 0.0]);
+    assert!(close(
+        Vec3::new(x.convert()[0], y.convert()[0], z.convert()[0]),
+        Vec3::new(0.0, 2.0, 2.0)
+    ));
+    assert!(x.convert()[1].is_nan());
+}
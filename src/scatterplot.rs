@@ -1,62 +1,1009 @@
+use std::collections::HashMap;
 use std::sync::Weak;
 
 use bevy::prelude::*;
-use itertools::izip;
+use bevy::render::view::NoFrustumCulling;
+use itertools::Itertools;
+use serde::Deserialize;
 
-use crate::feature::Feature;
+use crate::clock::{Clock, ClockPlugin};
+use crate::feature::{Feature, Pipe};
+use crate::instancing::{InstanceData, InstanceMaterialData, InstancingPlugin};
+use crate::labels::Label;
+use crate::meshutil::frustum;
+use crate::palette::mix;
+use crate::picking::Tooltip;
+use crate::theater::Theater;
+use crate::timeline::Timeline;
 
+/// Points in the Theater, positioned along its width (x), height (y) and depth (z).
+/// Features bound to an axis are fit to the Theater's range for it.
+/// Features with frames, and color_frames, play along with a Timeline if there is one.
+///
+/// The plugin keeps it as a resource, and the points follow it when it changes while the app runs:
+/// they're matched by name, so points still there move to their new places, new ones grow in,
+/// and the rest shrink away. Whether the points are instanced is settled when the plugin is added.
 #[derive(Clone)]
 pub struct Scatterplot {
-    pub lats: Feature,
-    pub lons: Feature,
-    pub alts: Feature,
+    pub x: Feature,
+    pub y: Feature,
+    pub z: Feature,
     pub sizes: Feature,
     pub colors: Vec<Color>,
+    /// The colors at each frame of a Timeline, if they change over time. The first is `colors`.
+    pub color_frames: Vec<Vec<Color>>,
+    /// What to show when hovering over each point. Points without one can't be picked.
+    pub tooltips: Vec<Tooltip>,
+    /// Text to write next to each point, if any. Needs the LabelPlugin.
+    pub labels: Vec<String>,
+    /// An id or name for each point, if any
+    pub names: Vec<String>,
+    /// Any other columns that came with each point
+    pub attributes: Vec<Attributes>,
+    /// Draw every point in one batch rather than as entities of their own.
+    /// This scales to hundreds of thousands of points, but they can't be picked.
+    pub instanced: bool,
+    /// The shape of each point. Points past the end are spheres.
+    pub glyphs: Vec<Glyph>,
+    /// Where each Column glyph rises from, in the Theater's space.
+    /// Columns past the end rise straight up from the Theater's floor.
+    pub bases: Vec<Vec3>,
+    /// Where the Column glyphs rise from at each frame of a Timeline, if they move. The first is `bases`.
+    pub base_frames: Vec<Vec<Vec3>>,
 }
+
+/// No points at all, e.g. until some stream in
+impl Default for Scatterplot {
+    fn default() -> Self {
+        let empty = || Pipe::new(0.0..=1.0, 0.0..=1.0).bundle(vec![]);
+        Scatterplot {
+            x: empty(),
+            y: empty(),
+            z: empty(),
+            sizes: empty(),
+            colors: vec![],
+            color_frames: vec![],
+            tooltips: vec![],
+            labels: vec![],
+            names: vec![],
+            attributes: vec![],
+            instanced: false,
+            glyphs: vec![],
+            bases: vec![],
+            base_frames: vec![],
+        }
+    }
+}
+
+/// Past this many points, plots should be instanced to keep a good frame rate
+pub const INSTANCING_THRESHOLD: usize = 5_000;
+
+/// The shape drawn for a point
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Glyph {
+    #[default]
+    Sphere,
+    Cube,
+    /// Pointing up
+    Cone,
+    /// A bar from the point's base up to the point itself, as wide as its size
+    Column,
+    /// Lying flat, like a coin
+    Disc,
+}
+impl std::str::FromStr for Glyph {
+    type Err = anyhow::Error;
+    fn from_str(name: &str) -> anyhow::Result<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "sphere" => Ok(Glyph::Sphere),
+            "cube" => Ok(Glyph::Cube),
+            "cone" => Ok(Glyph::Cone),
+            "column" | "cylinder" | "bar" => Ok(Glyph::Column),
+            "disc" | "disk" => Ok(Glyph::Disc),
+            _ => anyhow::bail!("There's no glyph named {}", name),
+        }
+    }
+}
+impl Glyph {
+    /// Glyphs to tell categories apart, in order. Columns mean something else, so they're left out.
+    pub const CATEGORIES: [Glyph; 4] = [Glyph::Sphere, Glyph::Cube, Glyph::Cone, Glyph::Disc];
+
+    /// The shape, one unit in radius. Columns stand one unit tall on the origin and the rest are
+    /// centered on it.
+    pub fn mesh(&self) -> Mesh {
+        match self {
+            Glyph::Sphere => shape::Icosphere {
+                radius: 1.0,
+                subdivisions: 3,
+            }
+            .into(),
+            // About as much volume as the sphere
+            Glyph::Cube => shape::Cube::new(1.6).into(),
+            Glyph::Cone => frustum(1.0, 0.0, -1.0, 1.0, 24),
+            Glyph::Column => frustum(1.0, 1.0, 0.0, 1.0, 24),
+            Glyph::Disc => frustum(1.0, 1.0, -0.15, 0.15, 24),
+        }
+    }
+
+    /// Where to put the mesh for a point of this size. Only columns care about the base.
+    pub fn transform(&self, point: Vec3, base: Vec3, size: f32) -> Transform {
+        match self {
+            Glyph::Column => {
+                let rise = point - base;
+                let rotation = match rise.try_normalize() {
+                    Some(up) => Quat::from_rotation_arc(Vec3::Y, up),
+                    None => Quat::IDENTITY,
+                };
+                Transform {
+                    translation: base,
+                    rotation,
+                    scale: Vec3::new(size, rise.length(), size),
+                }
+            }
+            _ => Transform::from_translation(point).with_scale(Vec3::splat(size)),
+        }
+    }
+}
+
+/// One point of a Scatterplot
 #[derive(Component, Clone)]
-pub struct ScatterplotPoint;
+pub struct ScatterplotPoint {
+    /// Where the point came from in the Scatterplot's Features
+    pub index: usize,
+    /// Its id or name, which may be empty
+    pub name: String,
+    /// What it's matched by when the Scatterplot changes, from Scatterplot::id
+    pub id: String,
+}
+
+/// One value from a column that isn't otherwise part of the plot
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum Attribute {
+    Bool(bool),
+    Number(f32),
+    Text(String),
+    Missing,
+}
+impl Attribute {
+    /// Guess the type of a value from a text file like a CSV.
+    /// Only plain numbers become numbers: "NaN", "inf" and zero-padded ids like "01" stay text.
+    pub fn parse(text: &str) -> Self {
+        let text = text.trim();
+        if text.is_empty() {
+            Attribute::Missing
+        } else if let Ok(b) = text.parse::<bool>() {
+            Attribute::Bool(b)
+        } else if let Some(n) = Self::plain_number(text) {
+            Attribute::Number(n)
+        } else {
+            Attribute::Text(text.into())
+        }
+    }
+
+    /// A finite number written without padding, which wouldn't change if it were written back out
+    fn plain_number(text: &str) -> Option<f32> {
+        let digits = text.strip_prefix('-').unwrap_or(text);
+        let padded = digits.len() > 1 && digits.starts_with('0') && !digits[1..].starts_with(['.', 'e', 'E']);
+        let plain = digits.starts_with(|c: char| c.is_ascii_digit() || c == '.') && !padded;
+        text.parse::<f32>().ok().filter(|n| plain && n.is_finite())
+    }
+}
+
+impl std::fmt::Display for Attribute {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Attribute::Bool(b) => write!(f, "{}", b),
+            Attribute::Number(n) => write!(f, "{}", n),
+            Attribute::Text(t) => write!(f, "{}", t),
+            Attribute::Missing => write!(f, "-"),
+        }
+    }
+}
+
+/// Every extra column of a point, by column name, so systems can filter or color by any of them
+#[derive(Component, Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct Attributes(pub HashMap<String, Attribute>);
+impl Attributes {
+    pub fn get(&self, key: &str) -> Option<&Attribute> {
+        self.0.get(key)
+    }
+    /// A column as a number, if it is one
+    pub fn number(&self, key: &str) -> Option<f32> {
+        match self.get(key)? {
+            Attribute::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+    /// A column as text, if it is some
+    pub fn text(&self, key: &str) -> Option<&str> {
+        match self.get(key)? {
+            Attribute::Text(t) => Some(t),
+            _ => None,
+        }
+    }
+    /// The columns in order by name, e.g. to list in a Tooltip
+    pub fn sorted(&self) -> impl Iterator<Item = (&String, &Attribute)> {
+        self.0.iter().sorted_by(|a, b| a.0.cmp(b.0))
+    }
+}
+/// The label of a point, which follows it as the Timeline plays
+#[derive(Component)]
+struct PointLabel {
+    index: usize,
+    id: String,
+    text: String,
+}
+
+/// The points drawn by one instanced entity, which all have the same glyph. Its instances are one
+/// for each index, in order, then any that are leaving.
+#[derive(Component, Default)]
+struct InstanceBatch {
+    glyph: Glyph,
+    indices: Vec<usize>,
+    /// The id of each point, to find it again when the Scatterplot changes
+    ids: Vec<String>,
+    /// Where each point was drawn when the Scatterplot changed, while it moves to its new place
+    from: Vec<InstanceData>,
+    /// Points that are no longer in the Scatterplot, shrinking away
+    leaving: Vec<InstanceData>,
+    /// How long the points have been moving, if they are
+    elapsed: Option<f32>,
+}
+
+/// How long points take to move, grow or shrink when the Scatterplot changes, in seconds
+const TRANSITION_SECONDS: f32 = 0.5;
+
+/// A point on its way from where it was to where the Scatterplot puts it now
+#[derive(Component)]
+struct Transition {
+    from: Transform,
+    from_color: Color,
+    elapsed: f32,
+}
+
+/// A point that's no longer in the Scatterplot, shrinking away before it's despawned
+#[derive(Component)]
+struct Leaving {
+    from_scale: Vec3,
+    elapsed: f32,
+}
+
+/// How far along a transition is, easing in and out
+fn ease(elapsed: f32) -> f32 {
+    let t = (elapsed / TRANSITION_SECONDS).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// Part of the way from one transform to another
+fn blend(from: &Transform, to: &Transform, t: f32) -> Transform {
+    Transform {
+        translation: from.translation.lerp(to.translation, t),
+        rotation: from.rotation.slerp(to.rotation, t),
+        scale: from.scale.lerp(to.scale, t),
+    }
+}
+
+/// The mesh of each glyph, shared by every point with it
+#[derive(Default)]
+struct GlyphMeshes(HashMap<Glyph, Handle<Mesh>>);
+impl GlyphMeshes {
+    fn get(&mut self, glyph: Glyph, meshes: &mut Assets<Mesh>) -> Handle<Mesh> {
+        self.0.entry(glyph).or_insert_with(|| meshes.add(glyph.mesh())).clone()
+    }
+}
+
+/// Where every point of a Scatterplot is, how big, and what color, at one moment
+struct Frame {
+    x: Vec<f32>,
+    y: Vec<f32>,
+    z: Vec<f32>,
+    sizes: Vec<f32>,
+    colors: Vec<Color>,
+    bases: Vec<Vec3>,
+    /// The size of the biggest point, which label priorities are measured against
+    biggest: f32,
+}
+impl Frame {
+    fn len(&self) -> usize {
+        [self.x.len(), self.y.len(), self.z.len(), self.sizes.len(), self.colors.len()]
+            .into_iter()
+            .min()
+            .unwrap_or_default()
+    }
+
+    /// Where to draw a point, or None if it's missing at this moment
+    fn transform(&self, plot: &Scatterplot, theater: &Theater, index: usize) -> Option<Transform> {
+        let (x, y, z, size) = (self.x[index], self.y[index], self.z[index], self.sizes[index]);
+        // Missing values, like categories outside a Band, have nowhere to go
+        if !(x.is_finite() && y.is_finite() && z.is_finite() && size.is_finite()) {
+            return None;
+        }
+        let base = self
+            .bases
+            .get(index)
+            .copied()
+            .unwrap_or_else(|| Vec3::new(x, *theater.height.start(), z));
+        Some(plot.glyph(index).transform(Vec3::new(x, y, z), base, size))
+    }
+
+    /// Just over a point, for a label this tall
+    fn label_anchor(&self, index: usize, height: f32) -> Vec3 {
+        Vec3::new(self.x[index], self.y[index] + self.sizes[index] + height, self.z[index])
+    }
+
+    /// The instances for some points, in order, with any that are missing shrunk to nothing
+    fn instances(&self, plot: &Scatterplot, theater: &Theater, indices: &[usize]) -> Vec<InstanceData> {
+        indices
+            .iter()
+            .map(|&index| match self.transform(plot, theater, index) {
+                Some(transform) => InstanceData::new(transform, self.colors[index]),
+                None => InstanceData::new(Transform::default(), self.colors[index]).shrunk(),
+            })
+            .collect()
+    }
+}
+
 impl Scatterplot {
+    /// Whether anything about the points changes over time
+    pub fn is_animated(&self) -> bool {
+        [&self.x, &self.y, &self.z, &self.sizes]
+            .iter()
+            .any(|feature| feature.is_animated())
+            || self.color_frames.len() > 1
+    }
+
+    fn glyph(&self, index: usize) -> Glyph {
+        self.glyphs.get(index).copied().unwrap_or_default()
+    }
+
+    /// Everything that can change about the points, at a fractional frame of a Timeline
+    fn frame_at(&self, theater: &Theater, frame: f32) -> Frame {
+        let convert = |feature: &Feature| {
+            let pipe = theater.pipe_for(feature);
+            feature.content_at(frame).into_iter().map(|v| pipe.apply(v)).collect()
+        };
+        let colors = match self.color_frames.len() {
+            0 | 1 => self.colors.clone(),
+            count => {
+                let frame = frame.clamp(0.0, (count - 1) as f32);
+                let before = &self.color_frames[frame.floor() as usize];
+                let after = &self.color_frames[frame.ceil() as usize];
+                before.iter().zip(after).map(|(a, b)| mix(*a, *b, frame.fract())).collect()
+            }
+        };
+        let bases = match self.base_frames.len() {
+            0 | 1 => self.bases.clone(),
+            count => {
+                let frame = frame.clamp(0.0, (count - 1) as f32);
+                let before = &self.base_frames[frame.floor() as usize];
+                let after = &self.base_frames[frame.ceil() as usize];
+                before.iter().zip(after).map(|(a, b)| a.lerp(*b, frame.fract())).collect()
+            }
+        };
+        let sizes: Vec<f32> = convert(&self.sizes);
+        Frame {
+            x: convert(&self.x),
+            y: convert(&self.y),
+            z: convert(&self.z),
+            biggest: sizes.iter().copied().filter(|s| s.is_finite()).fold(0.0, f32::max),
+            sizes,
+            colors,
+            bases,
+        }
+    }
+
+    /// What a point is matched by when the Scatterplot changes: its name, or its place if it has none
+    pub fn id(&self, index: usize) -> String {
+        match self.names.get(index) {
+            Some(name) if !name.is_empty() => name.clone(),
+            _ => format!("#{}", index),
+        }
+    }
+
+    /// Spawn one point as an entity of its own, hidden if it's missing for now
+    fn spawn_point(
+        &self,
+        commands: &mut Commands,
+        mesh: Handle<Mesh>,
+        materials: &mut Assets<StandardMaterial>,
+        frame: &Frame,
+        theater: &Theater,
+        index: usize,
+    ) -> Entity {
+        let transform = frame.transform(self, theater, index);
+        let material = materials.add(StandardMaterial {
+            base_color: frame.colors[index],
+            alpha_mode: AlphaMode::Blend,
+            unlit: false,
+            ..Default::default()
+        });
+
+        let mut point = commands.spawn();
+        point
+            .insert(ScatterplotPoint {
+                index,
+                name: self.names.get(index).cloned().unwrap_or_default(),
+                id: self.id(index),
+            })
+            .insert_bundle(PbrBundle {
+                mesh,
+                material,
+                transform: transform.unwrap_or_default(),
+                visibility: Visibility {
+                    is_visible: transform.is_some(),
+                },
+                ..Default::default()
+            });
+        if let Some(attributes) = self.attributes.get(index) {
+            point.insert(attributes.clone());
+        }
+        if let Some(tooltip) = self.tooltips.get(index) {
+            point.insert(tooltip.clone());
+        }
+        point.id()
+    }
+
+    /// Write a point's label just over it, if it has one
+    fn spawn_label(&self, commands: &mut Commands, asset_server: &AssetServer, frame: &Frame, index: usize) {
+        if let Some(text) = self.labels.get(index).filter(|label| !label.is_empty()) {
+            // Bigger points win any overlaps
+            let size = frame.sizes[index];
+            let height = (size * 1.5).max(0.04);
+            let anchor = frame.label_anchor(index, height);
+            let priority = Label::priority(size, frame.biggest);
+            let label = Label::spawn(commands, asset_server, text, anchor, height, priority);
+            commands.entity(label).insert(PointLabel {
+                index,
+                id: self.id(index),
+                text: text.clone(),
+            });
+        }
+    }
+
+    /// Draw every point in one batch for each glyph. Points drawn before, by id, move from there.
+    fn spawn_batches(
+        &self,
+        commands: &mut Commands,
+        meshes: &mut Assets<Mesh>,
+        frame: &Frame,
+        theater: &Theater,
+        drawn: Option<&HashMap<String, InstanceData>>,
+    ) {
+        for (glyph, indices) in (0..frame.len()).into_group_map_by(|&index| self.glyph(index)) {
+            let mut instances = frame.instances(self, theater, &indices);
+            let mut batch = InstanceBatch {
+                glyph,
+                ids: indices.iter().map(|&index| self.id(index)).collect(),
+                indices,
+                ..Default::default()
+            };
+            if let Some(drawn) = drawn {
+                // New points and hidden ones grow in where they're going
+                batch.from = (batch.ids.iter().zip(&instances))
+                    .map(|(id, to)| match drawn.get(id) {
+                        Some(from) if from.scale > 0.0 => *from,
+                        _ => to.shrunk(),
+                    })
+                    .collect();
+                batch.elapsed = Some(0.0);
+                instances = batch.from.clone();
+            }
+            commands.spawn().insert_bundle((
+                meshes.add(glyph.mesh()),
+                Transform::default(),
+                GlobalTransform::default(),
+                InstanceMaterialData(instances),
+                batch,
+                Visibility::default(),
+                ComputedVisibility::default(),
+                // Culling only sees the mesh at the origin, not the instances spread around it
+                NoFrustumCulling,
+            ));
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
     fn setup_points(
         plot: Res<Scatterplot>,
         mut commands: Commands,
         mut meshes: ResMut<Assets<Mesh>>,
         mut materials: ResMut<Assets<StandardMaterial>>,
+        asset_server: Res<AssetServer>,
+        theater: Res<Theater>,
+        timeline: Option<Res<Timeline>>,
     ) {
-        for (lat, lon, alt, size, base_color) in izip!(
-            plot.lats.convert(),
-            plot.lons.convert(),
-            plot.alts.convert(),
-            plot.sizes.convert(),
-            plot.colors.clone()
-        ) {
-            let material = materials.add(StandardMaterial {
-                base_color,
-                alpha_mode: AlphaMode::Blend,
-                unlit: false,
-                ..Default::default()
+        let frame = plot.frame_at(&theater, timeline.map_or(0.0, |timeline| timeline.frame));
+        // Animated points might only show up later, so they're all set up, hidden if need be
+        let animated = plot.is_animated();
+        // Points of each glyph share one mesh, scaled to their size
+        let mut glyph_meshes = GlyphMeshes::default();
+        for index in 0..frame.len() {
+            if frame.transform(&plot, &theater, index).is_none() && !animated {
+                continue;
+            }
+            plot.spawn_label(&mut commands, &asset_server, &frame, index);
+            if !plot.instanced {
+                let mesh = glyph_meshes.get(plot.glyph(index), &mut meshes);
+                plot.spawn_point(&mut commands, mesh, &mut materials, &frame, &theater, index);
+            }
+        }
+        if plot.instanced {
+            plot.spawn_batches(&mut commands, &mut meshes, &frame, &theater, None);
+        }
+        commands.insert_resource(glyph_meshes);
+    }
+
+    /// Match the points to the Scatterplot after it changes, by id: the points still in it move to
+    /// their new places, new ones grow in, and the ones that are gone shrink away
+    #[allow(clippy::too_many_arguments)]
+    fn sync_points(
+        plot: Res<Scatterplot>,
+        theater: Res<Theater>,
+        timeline: Option<Res<Timeline>>,
+        mut commands: Commands,
+        mut glyph_meshes: ResMut<GlyphMeshes>,
+        mut meshes: ResMut<Assets<Mesh>>,
+        mut materials: ResMut<Assets<StandardMaterial>>,
+        asset_server: Res<AssetServer>,
+        mut points: Query<SyncedPoint>,
+        mut labels: Query<(Entity, &mut PointLabel)>,
+        mut batches: Query<(Entity, &mut InstanceBatch, &InstanceMaterialData)>,
+    ) {
+        // It's new when the app starts, but setup_points takes care of that
+        if !plot.is_changed() || plot.is_added() {
+            return;
+        }
+        let frame = plot.frame_at(&theater, timeline.map_or(0.0, |timeline| timeline.frame));
+        let animated = plot.is_animated();
+        let shown = |index: usize| animated || frame.transform(&plot, &theater, index).is_some();
+        // The first point with each id claims it
+        let ids: HashMap<String, usize> = (0..frame.len()).rev().map(|index| (plot.id(index), index)).collect();
+
+        // The same points in the same batches just move from where they're drawn now
+        let grouped = match plot.instanced {
+            true => (0..frame.len()).into_group_map_by(|&index| plot.glyph(index)),
+            false => HashMap::new(),
+        };
+        let regrouped = batches.iter().count() == grouped.len()
+            && batches.iter().all(|(_, batch, _)| match grouped.get(&batch.glyph) {
+                Some(indices) => {
+                    batch.ids.len() == indices.len()
+                        && batch.ids.iter().zip(indices).all(|(id, &index)| *id == plot.id(index))
+                }
+                None => false,
             });
+        if plot.instanced && regrouped {
+            for (_, mut batch, instances) in batches.iter_mut() {
+                batch.indices = grouped[&batch.glyph].clone();
+                batch.from = instances.0.clone();
+                batch.elapsed = Some(0.0);
+            }
+        } else if plot.instanced {
+            // The points still in the plot move from where they're drawn now, in new batches, and each
+            // old batch is left with just its points that are gone, shrinking away
+            let mut drawn = HashMap::new();
+            for (entity, mut batch, instances) in batches.iter_mut() {
+                let (staying, gone): (Vec<_>, Vec<_>) = (batch.ids.iter().zip(&instances.0))
+                    .partition(|(id, _)| ids.contains_key(*id));
+                drawn.extend(staying.into_iter().map(|(id, instance)| (id.clone(), *instance)));
+                let leaving = (gone.into_iter().map(|(_, instance)| *instance))
+                    .chain(instances.0.iter().skip(batch.ids.len()).copied())
+                    .filter(|instance| instance.scale > 0.0)
+                    .collect_vec();
+                if leaving.is_empty() {
+                    commands.entity(entity).despawn();
+                } else {
+                    *batch = InstanceBatch {
+                        leaving,
+                        elapsed: Some(0.0),
+                        ..Default::default()
+                    };
+                }
+            }
+            plot.spawn_batches(&mut commands, &mut meshes, &frame, &theater, Some(&drawn));
+        } else {
+            let mut unclaimed = ids.clone();
+            for (entity, mut point, transform, visibility, material, mesh, tooltip, attributes) in points.iter_mut() {
+                let index = match unclaimed.remove(&point.id) {
+                    Some(index) => index,
+                    None => {
+                        commands
+                            .entity(entity)
+                            .remove::<ScatterplotPoint>()
+                            .remove::<Tooltip>()
+                            .remove::<Transition>()
+                            .insert(Leaving {
+                                from_scale: transform.scale,
+                                elapsed: 0.0,
+                            });
+                        continue;
+                    }
+                };
+                let target = frame.transform(&plot, &theater, index);
+                let glyph_mesh = glyph_meshes.get(plot.glyph(index), &mut meshes);
+                let from_color = materials.get(material).map_or(frame.colors[index], |m| m.base_color);
+                // Points that are already what the plot makes them are left alone, so a change to a
+                // few points only touches those
+                let placed = match (visibility.is_visible, target) {
+                    (true, Some(target)) => target == *transform,
+                    (visible, target) => !visible && target.is_none(),
+                };
+                let mut changes = commands.entity(entity);
+                if !(placed && from_color == frame.colors[index] && *mesh == glyph_mesh) {
+                    // Hidden points grow in where they're going, rather than flying in from where they were
+                    let from = match (visibility.is_visible, target) {
+                        (false, Some(target)) => target.with_scale(Vec3::ZERO),
+                        _ => *transform,
+                    };
+                    changes
+                        .insert(Transition {
+                            from,
+                            from_color,
+                            elapsed: 0.0,
+                        })
+                        .insert(glyph_mesh);
+                }
+                if plot.tooltips.get(index) != tooltip {
+                    match plot.tooltips.get(index) {
+                        Some(tooltip) => changes.insert(tooltip.clone()),
+                        None => changes.remove::<Tooltip>(),
+                    };
+                }
+                if plot.attributes.get(index) != attributes {
+                    match plot.attributes.get(index) {
+                        Some(attributes) => changes.insert(attributes.clone()),
+                        None => changes.remove::<Attributes>(),
+                    };
+                }
+                point.index = index;
+                point.name = plot.names.get(index).cloned().unwrap_or_default();
+            }
+            for index in unclaimed.into_values().sorted() {
+                if !shown(index) {
+                    continue;
+                }
+                let mesh = glyph_meshes.get(plot.glyph(index), &mut meshes);
+                let point = plot.spawn_point(&mut commands, mesh, &mut materials, &frame, &theater, index);
+                // New points grow from nothing, from the moment they're drawn
+                let from = frame.transform(&plot, &theater, index).unwrap_or_default().with_scale(Vec3::ZERO);
+                commands.entity(point).insert(from).insert(Transition {
+                    from,
+                    from_color: frame.colors[index],
+                    elapsed: 0.0,
+                });
+            }
+        }
 
-            commands
-                .spawn()
-                .insert_bundle(PbrBundle {
-                    mesh: meshes.add(
-                        shape::Icosphere {
-                            radius: size,
-                            subdivisions: 3,
-                        }
-                        .into(),
-                    ),
-                    material,
-                    transform: Transform::from_translation(Vec3::from([lon, alt, lat])),
-                    ..Default::default()
-                })
-                .insert(ScatterplotPoint);
+        // Labels follow their points, and any with new text are written again
+        let mut written = vec![false; frame.len()];
+        for (entity, mut label) in labels.iter_mut() {
+            match ids.get(&label.id) {
+                Some(&index) if !written[index] && plot.labels.get(index) == Some(&label.text) => {
+                    label.index = index;
+                    written[index] = true;
+                }
+                _ => commands.entity(entity).despawn_recursive(),
+            }
+        }
+        for index in (0..frame.len()).filter(|&index| !written[index] && shown(index)) {
+            plot.spawn_label(&mut commands, &asset_server, &frame, index);
         }
     }
+
+    /// Move, resize and recolor the points as the Timeline plays, or as they move after the
+    /// Scatterplot changes
+    #[allow(clippy::too_many_arguments)]
+    fn place_points(
+        plot: Res<Scatterplot>,
+        theater: Res<Theater>,
+        timeline: Option<Res<Timeline>>,
+        clock: Res<Clock>,
+        mut commands: Commands,
+        mut materials: ResMut<Assets<StandardMaterial>>,
+        mut points: Query<PlacedPoint>,
+        mut batches: Query<(Entity, &mut InstanceBatch, &mut InstanceMaterialData)>,
+        mut labels: Query<(&PointLabel, &mut Label)>,
+    ) {
+        let playing = timeline.as_ref().is_some_and(|timeline| timeline.is_changed()) && plot.is_animated();
+        let moving = points.iter().any(|(.., transition)| transition.is_some())
+            || batches.iter().any(|(_, batch, _)| batch.elapsed.is_some());
+        if !(playing || moving || plot.is_changed()) {
+            return;
+        }
+        let frame = plot.frame_at(&theater, timeline.map_or(0.0, |timeline| timeline.frame));
+        // sync_points just matched the points to the change, but the transitions it started only land
+        // at the end of the stage, so until then the points without one stay where they are
+        let synced = plot.is_changed() && !plot.is_added();
+        for (entity, point, mut transform, mut visibility, material, transition) in points.iter_mut() {
+            if synced && transition.is_none() {
+                continue;
+            }
+            let mut placed = frame.transform(&plot, &theater, point.index);
+            let mut color = frame.colors[point.index];
+            if let Some(mut transition) = transition {
+                transition.elapsed += clock.delta_seconds();
+                let t = ease(transition.elapsed);
+                placed = placed.map(|placed| blend(&transition.from, &placed, t));
+                color = mix(transition.from_color, color, t);
+                if transition.elapsed >= TRANSITION_SECONDS {
+                    commands.entity(entity).remove::<Transition>();
+                }
+            }
+            match placed {
+                Some(placed) => {
+                    *transform = placed;
+                    visibility.is_visible = true;
+                }
+                None => visibility.is_visible = false,
+            }
+            // Only touch materials that change, since each one changed is sent to the GPU again
+            if materials.get(material).is_some_and(|m| m.base_color != color) {
+                if let Some(material) = materials.get_mut(material) {
+                    material.base_color = color;
+                }
+            }
+        }
+        for (entity, mut batch, mut instances) in batches.iter_mut() {
+            if !(playing || plot.is_changed() || batch.elapsed.is_some()) {
+                continue;
+            }
+            let mut placed = frame.instances(&plot, &theater, &batch.indices);
+            if let Some(elapsed) = batch.elapsed.map(|elapsed| elapsed + clock.delta_seconds()) {
+                let t = ease(elapsed);
+                for (to, from) in placed.iter_mut().zip(&batch.from) {
+                    *to = from.lerp(to, t);
+                }
+                placed.extend(batch.leaving.iter().map(|leaving| leaving.lerp(&leaving.shrunk(), t)));
+                if elapsed < TRANSITION_SECONDS {
+                    batch.elapsed = Some(elapsed);
+                } else if batch.indices.is_empty() {
+                    commands.entity(entity).despawn();
+                } else {
+                    batch.elapsed = None;
+                    batch.from.clear();
+                    batch.leaving.clear();
+                }
+            }
+            instances.0 = placed;
+        }
+        if playing || plot.is_changed() {
+            for (point, mut label) in labels.iter_mut() {
+                label.anchor = frame.label_anchor(point.index, label.size.y);
+            }
+        }
+    }
+
+    /// Shrink away the points that are no longer in the Scatterplot, then despawn them
+    fn remove_points(clock: Res<Clock>, mut commands: Commands, mut leaving: Query<(Entity, &mut Leaving, &mut Transform)>) {
+        for (entity, mut leaving, mut transform) in leaving.iter_mut() {
+            leaving.elapsed += clock.delta_seconds();
+            if leaving.elapsed >= TRANSITION_SECONDS {
+                commands.entity(entity).despawn_recursive();
+            } else {
+                transform.scale = leaving.from_scale * (1.0 - ease(leaving.elapsed));
+            }
+        }
+    }
+}
+
+/// The Scatterplot's systems, so others can be ordered around them. Systems that change the
+/// Scatterplot run before Sync, so its points are never placed by indices that are gone.
+#[derive(SystemLabel, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ScatterplotSystem {
+    /// Matching the points to the Scatterplot after it changes
+    Sync,
+    /// Moving, resizing and recoloring the points
+    Place,
 }
+
+/// What sync_points needs to know about each point
+type SyncedPoint<'a> = (
+    Entity,
+    &'a mut ScatterplotPoint,
+    &'a Transform,
+    &'a Visibility,
+    &'a Handle<StandardMaterial>,
+    &'a Handle<Mesh>,
+    Option<&'a Tooltip>,
+    Option<&'a Attributes>,
+);
+
+/// What place_points needs to know about each point
+type PlacedPoint<'a> = (
+    Entity,
+    &'a ScatterplotPoint,
+    &'a mut Transform,
+    &'a mut Visibility,
+    &'a Handle<StandardMaterial>,
+    Option<&'a mut Transition>,
+);
+
 impl Plugin for Scatterplot {
     fn build(&self, app: &mut App) {
-        app.insert_resource(self.clone());
-        app.add_startup_system(Scatterplot::setup_points);
+        // Points are placed in the Theater, so without one they get the default stage
+        app.insert_resource(self.clone())
+            .init_resource::<Theater>()
+            .add_plugin(ClockPlugin);
+        if self.instanced {
+            app.add_plugin(InstancingPlugin);
+        }
+        app.add_startup_system(Scatterplot::setup_points)
+            .add_system(Scatterplot::sync_points.label(ScatterplotSystem::Sync))
+            .add_system(
+                Scatterplot::place_points
+                    .label(ScatterplotSystem::Place)
+                    .after(ScatterplotSystem::Sync),
+            )
+            .add_system(Scatterplot::remove_points);
+    }
+}
+
+#[test]
+fn test_attributes() {
+    let attributes: Attributes =
+        serde_json::from_str(r#"{"state": "Texas", "capital": false, "founded": 1837, "mayor": null}"#)
+            .unwrap();
+    assert_eq!(attributes.text("state"), Some("Texas"));
+    assert_eq!(attributes.get("capital"), Some(&Attribute::Bool(false)));
+    assert_eq!(attributes.number("founded"), Some(1837.0));
+    assert_eq!(attributes.number("state"), None);
+    assert_eq!(attributes.get("mayor"), Some(&Attribute::Missing));
+    assert_eq!(
+        attributes.sorted().map(|(k, v)| format!("{}={}", k, v)).join(" "),
+        "capital=false founded=1837 mayor=- state=Texas"
+    );
+}
+
+#[test]
+fn test_parse_attributes() {
+    assert_eq!(Attribute::parse(" 1.50 "), Attribute::Number(1.5));
+    assert_eq!(Attribute::parse("-0.25"), Attribute::Number(-0.25));
+    assert_eq!(Attribute::parse("0"), Attribute::Number(0.0));
+    // Zero-padded ids like FIPS codes keep their padding, and so can still be matched
+    assert_eq!(Attribute::parse("01"), Attribute::Text("01".into()));
+    assert_eq!(Attribute::parse("-007"), Attribute::Text("-007".into()));
+    for text in ["NaN", "inf", "-infinity"] {
+        assert_eq!(Attribute::parse(text), Attribute::Text(text.into()));
+    }
+}
+
+#[test]
+fn test_glyphs() {
+    assert_eq!("Cylinder".parse::<Glyph>().unwrap(), Glyph::Column);
+    assert!("blob".parse::<Glyph>().is_err());
+    // Columns run from their base up to the point, on a plane or leaning out of a globe
+    for (base, point) in [(Vec3::new(1.0, 0.0, 2.0), Vec3::new(1.0, 3.0, 2.0)), (Vec3::ZERO, Vec3::ONE)] {
+        let transform = Glyph::Column.transform(point, base, 0.1);
+        assert!((transform * Vec3::ZERO - base).length() < 1e-5);
+        assert!((transform * Vec3::Y - point).length() < 1e-5);
+    }
+    let transform = Glyph::Cube.transform(Vec3::ONE, Vec3::ZERO, 0.5);
+    assert_eq!(transform * Vec3::X, Vec3::new(1.5, 1.0, 1.0));
+}
+
+#[test]
+fn test_moving_bases() {
+    let plot = Scatterplot {
+        bases: vec![Vec3::ZERO],
+        base_frames: vec![vec![Vec3::ZERO], vec![Vec3::X]],
+        ..Scatterplot::default()
+    };
+    // Columns slide along the ground between frames, with their points
+    assert_eq!(plot.frame_at(&Theater::default(), 0.5).bases, vec![Vec3::new(0.5, 0.0, 0.0)]);
+    assert_eq!(plot.frame_at(&Theater::default(), 1.0).bases, vec![Vec3::X]);
+}
+
+#[test]
+fn test_live_updates() {
+    let plot = |names: &[&str]| {
+        let feature = || Pipe::new(0.0..=1.0, 0.0..=1.0).bundle(vec![0.5; names.len()]);
+        Scatterplot {
+            x: Pipe::new(0.0..=3.0, 0.0..=3.0).bundle((0..names.len()).map(|i| i as f32).collect()),
+            y: feature(),
+            z: feature(),
+            sizes: feature(),
+            colors: vec![Color::WHITE; names.len()],
+            names: names.iter().map(|name| name.to_string()).collect(),
+            ..Scatterplot::default()
+        }
+    };
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .add_plugin(bevy::asset::AssetPlugin)
+        .add_asset::<Mesh>()
+        .add_asset::<StandardMaterial>()
+        // No Theater, so the points go on the default stage
+        .add_plugin(plot(&["a", "b", "c"]));
+    app.update();
+    assert_eq!(app.world.query::<&ScatterplotPoint>().iter(&app.world).count(), 3);
+
+    // Points that stay put are left alone, even as their tooltips change
+    *app.world.resource_mut::<Scatterplot>() = Scatterplot {
+        tooltips: vec![Tooltip::new("a"), Tooltip::new("b"), Tooltip::new("c")],
+        ..plot(&["a", "b", "c"])
+    };
+    app.update();
+    assert_eq!(app.world.query::<&Transition>().iter(&app.world).count(), 0);
+    assert_eq!(app.world.query::<&Tooltip>().iter(&app.world).count(), 3);
+
+    // b and c stay as they were, moved to the front, a leaves and d arrives
+    *app.world.resource_mut::<Scatterplot>() = plot(&["b", "c", "d"]);
+    app.update();
+    let points = app
+        .world
+        .query::<(&ScatterplotPoint, Option<&Transition>)>()
+        .iter(&app.world)
+        .map(|(point, transition)| (point.id.clone(), point.index, transition.is_some()))
+        .sorted()
+        .collect_vec();
+    assert_eq!(
+        points,
+        vec![("b".into(), 0, true), ("c".into(), 1, true), ("d".into(), 2, true)]
+    );
+    assert_eq!(app.world.query::<&Leaving>().iter(&app.world).count(), 1);
+
+    // Instanced points move the same way, inside their batches
+    let instanced = |names: &[&str]| Scatterplot {
+        instanced: true,
+        ..plot(names)
+    };
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .add_plugin(bevy::asset::AssetPlugin)
+        .add_asset::<Mesh>()
+        .add_asset::<StandardMaterial>()
+        .add_plugin(instanced(&["a", "b", "c"]));
+    app.update();
+    *app.world.resource_mut::<Scatterplot>() = instanced(&["b", "c", "d"]);
+    app.update();
+    let batches = app
+        .world
+        .query::<(&InstanceBatch, &InstanceMaterialData)>()
+        .iter(&app.world)
+        .map(|(batch, instances)| (batch.ids.clone(), batch.elapsed.is_some(), instances.0.len()))
+        .sorted_by_key(|(ids, ..)| ids.len())
+        .collect_vec();
+    // a shrinks away in what's left of the old batch, while d grows in
+    assert_eq!(
+        batches,
+        vec![(vec![], true, 1), (vec!["b".into(), "c".into(), "d".into()], true, 3)]
+    );
+    let old = app.world.query::<&InstanceBatch>().iter(&app.world).find(|batch| batch.ids.is_empty());
+    assert!(old.unwrap().leaving[0].scale > 0.0);
+
+    // A system that swaps in a smaller plot runs before the points are matched to it, so none of
+    // them are placed by an index that's gone
+    for instanced in [false, true] {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_plugin(bevy::asset::AssetPlugin)
+            .add_asset::<Mesh>()
+            .add_asset::<StandardMaterial>()
+            .add_plugin(Scatterplot {
+                instanced,
+                ..plot(&["a", "b", "c"])
+            })
+            .add_system(
+                (move |mut current: ResMut<Scatterplot>, mut updates: Local<usize>| {
+                    *updates += 1;
+                    if *updates == 2 {
+                        *current = Scatterplot {
+                            instanced,
+                            ..plot(&["c"])
+                        };
+                    }
+                })
+                .before(ScatterplotSystem::Sync),
+            );
+        for _ in 0..3 {
+            app.update();
+        }
+        let placed = match instanced {
+            false => app.world.query::<&ScatterplotPoint>().iter(&app.world).map(|point| point.index).collect_vec(),
+            true => app.world.query::<&InstanceBatch>().iter(&app.world).flat_map(|batch| batch.indices.clone()).collect(),
+        };
+        assert_eq!(placed, vec![0]);
     }
 }
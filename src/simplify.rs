@@ -0,0 +1,368 @@
+use std::collections::{HashMap, HashSet};
+
+use bevy::prelude::*;
+use itertools::Itertools;
+
+/// Points are matched exactly, since neighbors in a GeoJSON layer repeat the same coordinates
+type Key = (u32, u32);
+fn key(p: Vec2) -> Key {
+    (p.x.to_bits(), p.y.to_bits())
+}
+
+/// How far a point is from the segment AB
+fn distance_to_segment(p: Vec2, a: Vec2, b: Vec2) -> f32 {
+    let along = b - a;
+    let t = if along.length_squared() > 0.0 {
+        ((p - a).dot(along) / along.length_squared()).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    p.distance(a + along * t)
+}
+
+/// Douglas-Peucker: keep the ends, and recursively keep whichever point strays furthest from the
+/// line between the kept points, until nothing strays further than the tolerance
+fn simplify_line(points: &[Vec2], tolerance: f32) -> Vec<Vec2> {
+    if points.len() < 3 {
+        return points.to_vec();
+    }
+    let mut keep = vec![false; points.len()];
+    keep[0] = true;
+    keep[points.len() - 1] = true;
+    let mut spans = vec![(0, points.len() - 1)];
+    while let Some((first, last)) = spans.pop() {
+        let furthest = (first + 1..last)
+            .map(|i| (i, distance_to_segment(points[i], points[first], points[last])))
+            .fold(None, |best: Option<(usize, f32)>, (i, d)| match best {
+                Some((_, best_d)) if best_d >= d => best,
+                _ => Some((i, d)),
+            });
+        if let Some((i, d)) = furthest {
+            if d > tolerance {
+                keep[i] = true;
+                spans.push((first, i));
+                spans.push((i, last));
+            }
+        }
+    }
+    points
+        .iter()
+        .zip(keep)
+        .filter(|(_, kept)| *kept)
+        .map(|(p, _)| *p)
+        .collect()
+}
+
+/// Which way to walk an arc so it's simplified the same way from either end,
+/// and so both neighbors along a border get the same result
+fn is_forward(arc: &[Vec2]) -> bool {
+    match (arc.first(), arc.last()) {
+        (Some(&first), Some(&last)) if first != last => key(first) <= key(last),
+        // A loop starts and ends at the same place, so look at the next points in
+        _ => arc.len() < 3 || key(arc[1]) <= key(arc[arc.len() - 2]),
+    }
+}
+
+/// Which side of the line AB the point P is on: positive to the left, negative to the right
+fn orientation(a: Vec2, b: Vec2, p: Vec2) -> f32 {
+    (b - a).perp_dot(p - a)
+}
+
+/// Whether segments AB and CD cross or touch, apart from meeting end to end
+fn segments_cross(a: Vec2, b: Vec2, c: Vec2, d: Vec2) -> bool {
+    if a == c || a == d || b == c || b == d {
+        return false;
+    }
+    let (o1, o2) = (orientation(a, b, c), orientation(a, b, d));
+    let (o3, o4) = (orientation(c, d, a), orientation(c, d, b));
+    let within = |p: Vec2, a: Vec2, b: Vec2| p.cmpge(a.min(b)).all() && p.cmple(a.max(b)).all();
+    (o1 * o2 < 0.0 && o3 * o4 < 0.0)
+        || (o1 == 0.0 && within(c, a, b))
+        || (o2 == 0.0 && within(d, a, b))
+        || (o3 == 0.0 && within(a, c, d))
+        || (o4 == 0.0 && within(b, c, d))
+}
+
+/// The corners of a box around some points
+fn bounds(points: &[Vec2]) -> (Vec2, Vec2) {
+    points.iter().fold(
+        (Vec2::splat(f32::INFINITY), Vec2::splat(f32::NEG_INFINITY)),
+        |(low, high), p| (low.min(*p), high.max(*p)),
+    )
+}
+
+/// Whether two lines cross each other, or one crosses itself when they're the same line
+fn lines_cross(left: &[Vec2], right: &[Vec2], same: bool) -> bool {
+    left.iter().tuple_windows().enumerate().any(|(i, (a, b))| {
+        right
+            .iter()
+            .tuple_windows()
+            .enumerate()
+            .filter(|(j, _)| !same || *j > i)
+            .any(|(_, (c, d))| segments_cross(*a, *b, *c, *d))
+    })
+}
+
+/// Every arc that crosses itself or another arc
+fn crossing_arcs(arcs: &[Vec<Vec2>]) -> HashSet<usize> {
+    // Sweep across the boxes from left to right, so only arcs that overlap are compared
+    let boxes = arcs.iter().map(|arc| bounds(arc)).collect_vec();
+    let order = (0..arcs.len())
+        .sorted_by(|&a, &b| boxes[a].0.x.total_cmp(&boxes[b].0.x))
+        .collect_vec();
+    let mut crossing = HashSet::new();
+    for (k, &a) in order.iter().enumerate() {
+        if lines_cross(&arcs[a], &arcs[a], true) {
+            crossing.insert(a);
+        }
+        for &b in order[k + 1..].iter().take_while(|&&b| boxes[b].0.x <= boxes[a].1.x) {
+            let overlap = boxes[b].0.y <= boxes[a].1.y && boxes[a].0.y <= boxes[b].1.y;
+            if overlap && lines_cross(&arcs[a], &arcs[b], false) {
+                crossing.insert(a);
+                crossing.insert(b);
+            }
+        }
+    }
+    crossing
+}
+
+/// Below this, a tolerance keeps every point that matters, so arcs go back to their originals
+const SMALLEST_TOLERANCE: f32 = 1e-6;
+
+/// Simplify a set of polygons together, so that borders shared between neighbors stay watertight.
+///
+/// Every ring is split into arcs at junctions: points where the rings that share them part ways.
+/// Junctions are always kept, and each arc is simplified once, for every ring it's part of.
+/// An arc that would cross itself or another arc is simplified less, and so is a ring that would
+/// collapse below a triangle, so the result keeps the topology of the originals.
+pub fn simplify_shared(polygons: &[geo::Polygon<f32>], tolerance: f32) -> Vec<geo::Polygon<f32>> {
+    if tolerance <= 0.0 {
+        return polygons.to_vec();
+    }
+    let rings = |polygon: &geo::Polygon<f32>| {
+        std::iter::once(polygon.exterior())
+            .chain(polygon.interiors())
+            .map(|ring| {
+                let mut points = ring.points().map(|p| Vec2::new(p.x(), p.y())).collect_vec();
+                points.dedup();
+                if points.len() > 1 && points.first() == points.last() {
+                    points.pop();
+                }
+                points
+            })
+            .collect_vec()
+    };
+    let all_rings = polygons.iter().map(rings).collect_vec();
+
+    // Find the neighbors of every point, across every ring
+    let mut neighbors: HashMap<Key, HashSet<Key>> = HashMap::new();
+    for ring in all_rings.iter().flatten() {
+        for (a, b, c) in ring.iter().circular_tuple_windows() {
+            let around = neighbors.entry(key(*b)).or_default();
+            around.insert(key(*a));
+            around.insert(key(*c));
+        }
+    }
+    let is_junction = |p: Vec2| neighbors.get(&key(p)).is_some_and(|n| n.len() > 2);
+
+    // Split every ring into arcs, walked the same way wherever they're shared.
+    // Each ring becomes a list of (arc, reversed), and arcs the same from both sides are stored once.
+    let mut arcs: Vec<Vec<Vec2>> = vec![];
+    let mut arc_ids: HashMap<Vec<Key>, usize> = HashMap::new();
+    let mut tolerances = vec![];
+    let mut add_arc = |mut arc: Vec<Vec2>, tolerance: f32| {
+        let reversed = !is_forward(&arc);
+        if reversed {
+            arc.reverse();
+        }
+        let id = *arc_ids.entry(arc.iter().map(|p| key(*p)).collect()).or_insert_with(|| {
+            arcs.push(arc);
+            tolerances.push(tolerance);
+            arcs.len() - 1
+        });
+        (id, reversed)
+    };
+    let ring_arcs = all_rings
+        .iter()
+        .map(|rings| {
+            rings
+                .iter()
+                .map(|ring| {
+                    // Rings this small are left as they are, but they're still in the way of others
+                    if ring.len() < 4 {
+                        let mut closed = ring.clone();
+                        closed.extend(ring.first());
+                        return vec![add_arc(closed, 0.0)];
+                    }
+                    let junctions = (0..ring.len()).filter(|&i| is_junction(ring[i])).collect_vec();
+                    // A ring that touches nothing else gets one arc, anchored at a point any copy of it would choose
+                    let starts = if junctions.is_empty() {
+                        vec![(0..ring.len()).min_by_key(|&i| key(ring[i])).unwrap_or(0)]
+                    } else {
+                        junctions
+                    };
+                    (0..starts.len())
+                        .map(|k| {
+                            let (start, end) = (starts[k], starts[(k + 1) % starts.len()]);
+                            let length = (end + ring.len() - start - 1) % ring.len() + 1;
+                            let arc = (0..=length).map(|i| ring[(start + i) % ring.len()]).collect_vec();
+                            add_arc(arc, tolerance)
+                        })
+                        .collect_vec()
+                })
+                .collect_vec()
+        })
+        .collect_vec();
+
+    // Put a ring back together from its simplified arcs
+    let join = |simplified: &[Vec<Vec2>], arcs: &[(usize, bool)]| {
+        let mut ring = vec![];
+        for &(id, reversed) in arcs {
+            let mut arc = simplified[id].clone();
+            if reversed {
+                arc.reverse();
+            }
+            // The end of each arc is the start of the next
+            ring.extend_from_slice(&arc[..arc.len().saturating_sub(1)]);
+        }
+        ring
+    };
+
+    // Simplify less wherever the result would tangle, until nothing does
+    let mut simplified = arcs
+        .iter()
+        .zip(&tolerances)
+        .map(|(arc, &tolerance)| match tolerance > 0.0 {
+            true => simplify_line(arc, tolerance),
+            false => arc.clone(),
+        })
+        .collect_vec();
+    loop {
+        let mut tangled = crossing_arcs(&simplified);
+        for ring in ring_arcs.iter().flatten() {
+            if join(&simplified, ring).len() < 3 {
+                tangled.extend(ring.iter().map(|(id, _)| *id));
+            }
+        }
+        tangled.retain(|&id| tolerances[id] > 0.0);
+        if tangled.is_empty() {
+            break;
+        }
+        for id in tangled {
+            tolerances[id] /= 2.0;
+            if tolerances[id] < SMALLEST_TOLERANCE {
+                tolerances[id] = 0.0;
+                simplified[id] = arcs[id].clone();
+            } else {
+                simplified[id] = simplify_line(&arcs[id], tolerances[id]);
+            }
+        }
+    }
+
+    ring_arcs
+        .iter()
+        .map(|rings| {
+            let mut rings = rings.iter().map(|ring| {
+                let mut ring = join(&simplified, ring);
+                if let Some(&first) = ring.first() {
+                    ring.push(first);
+                }
+                geo::LineString::from(ring.into_iter().map(|p| (p.x, p.y)).collect_vec())
+            });
+            let exterior = rings.next().unwrap_or_else(|| geo::LineString::new(vec![]));
+            geo::Polygon::new(exterior, rings.collect())
+        })
+        .collect()
+}
+
+#[test]
+fn test_simplify_line() {
+    let line = [
+        Vec2::new(0.0, 0.0),
+        Vec2::new(1.0, 0.1),
+        Vec2::new(2.0, -0.1),
+        Vec2::new(3.0, 5.0),
+        Vec2::new(4.0, 6.0),
+        Vec2::new(5.0, 7.0),
+    ];
+    assert_eq!(
+        simplify_line(&line, 0.5),
+        vec![
+            Vec2::new(0.0, 0.0),
+            Vec2::new(2.0, -0.1),
+            Vec2::new(3.0, 5.0),
+            Vec2::new(5.0, 7.0)
+        ]
+    );
+    // Even with no tolerance, points on a straight line add nothing
+    assert_eq!(simplify_line(&line, 0.0).len(), line.len() - 1);
+}
+
+#[test]
+fn test_simplify_shared_borders() {
+    // Two squares sharing a wiggly border along x = 1, walked in opposite directions
+    let border = (0..=10)
+        .map(|i| (1.0 + 0.01 * ((i * 7) % 3) as f32, i as f32 / 10.0))
+        .collect_vec();
+    let mut left = vec![(0.0f32, 0.0)];
+    left.extend(border.iter().copied());
+    left.push((0.0, 1.0));
+    let mut right = border.clone();
+    right.reverse();
+    right.extend([(2.0, 1.0), (2.0, 0.0)]);
+    let polygons = [
+        geo::Polygon::new(geo::LineString::from(left), vec![]),
+        geo::Polygon::new(geo::LineString::from(right), vec![]),
+    ];
+
+    let simplified = simplify_shared(&polygons, 0.05);
+    let points = |polygon: &geo::Polygon<f32>| {
+        polygon
+            .exterior()
+            .points()
+            .map(|p| key(Vec2::new(p.x(), p.y())))
+            .collect::<HashSet<_>>()
+    };
+    let (left, right) = (points(&simplified[0]), points(&simplified[1]));
+    // The wiggles are gone, and what's left of the border is the same on both sides
+    assert!(left.len() < 8 && right.len() < 8);
+    let on_border = |p: &&Key| f32::from_bits(p.0) >= 1.0 && f32::from_bits(p.0) <= 1.02;
+    let left_border = left.iter().filter(on_border).collect::<HashSet<_>>();
+    let right_border = right.iter().filter(on_border).collect::<HashSet<_>>();
+    assert_eq!(left_border, right_border);
+    // The corners where the squares meet are junctions, so they stay
+    for (x, y) in [border[0], border[10]] {
+        assert!(left_border.contains(&key(Vec2::new(x, y))));
+    }
+    assert_eq!(simplified[0].exterior().0.first(), simplified[0].exterior().0.last());
+}
+
+#[test]
+fn test_simplify_keeps_small_rings() {
+    let triangle = geo::Polygon::new(
+        geo::LineString::from(vec![(0.0f32, 0.0), (1.0, 0.0), (0.0, 1.0)]),
+        vec![],
+    );
+    let simplified = simplify_shared(std::slice::from_ref(&triangle), 10.0);
+    assert_eq!(simplified[0], triangle);
+}
+
+#[test]
+fn test_simplify_keeps_topology() {
+    // A square with a spike out of the bottom, and a hole reaching down into the spike
+    let exterior = vec![(0.0f32, 0.0), (4.0, 0.0), (5.0, -3.0), (6.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.0, 10.0)];
+    let hole = vec![(4.9f32, -2.0), (5.1, -2.0), (5.1, 0.5), (4.9, 0.5)];
+    let spike = key(Vec2::new(5.0, -3.0));
+    let has_spike = |polygon: &geo::Polygon<f32>| {
+        polygon.exterior().points().any(|p| key(Vec2::new(p.x(), p.y())) == spike)
+    };
+
+    // On its own, the spike is within the tolerance
+    let solid = geo::Polygon::new(geo::LineString::from(exterior.clone()), vec![]);
+    assert!(!has_spike(&simplify_shared(&[solid], 4.0)[0]));
+    // But cutting it off would cut through the hole
+    let holed = geo::Polygon::new(geo::LineString::from(exterior), vec![geo::LineString::from(hole)]);
+    let simplified = simplify_shared(&[holed], 4.0);
+    assert!(has_spike(&simplified[0]));
+    assert!(simplified[0].interiors()[0].0.len() >= 4);
+}
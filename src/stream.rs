@@ -0,0 +1,194 @@
+use std::io::{BufRead, BufReader};
+use std::net::TcpListener;
+use std::path::PathBuf;
+
+use anyhow::{bail, Context, Result};
+use bevy::app::AppExit;
+use bevy::prelude::*;
+use tokio::sync::mpsc::{channel, error::TrySendError, Receiver, Sender};
+
+use crate::table::Row;
+
+/// Where to read rows from while the app runs, one JSON object per line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    Stdin,
+    /// A Unix socket to listen on. Any number of writers can connect to it.
+    Unix(PathBuf),
+    /// A TCP address to listen on, like 127.0.0.1:7878
+    Tcp(String),
+}
+impl std::str::FromStr for Source {
+    type Err = anyhow::Error;
+    /// "-" or "stdin", "unix:PATH", or "tcp:ADDRESS", where a bare port listens on localhost
+    fn from_str(source: &str) -> anyhow::Result<Self> {
+        let tcp = |address: &str| match address.parse::<u16>() {
+            Ok(port) => Source::Tcp(format!("127.0.0.1:{}", port)),
+            Err(_) => Source::Tcp(address.into()),
+        };
+        match source.split_once(':') {
+            _ if source == "-" || source == "stdin" => Ok(Source::Stdin),
+            Some(("unix", path)) if !path.is_empty() => Ok(Source::Unix(path.into())),
+            Some(("tcp", address)) if !address.is_empty() => Ok(tcp(address)),
+            _ if source.parse::<u16>().is_ok() => Ok(tcp(source)),
+            _ => bail!("Can't stream from {}. Try -, unix:PATH, or tcp:PORT", source),
+        }
+    }
+}
+
+/// How many rows can wait to be taken in. Past this, rows are dropped rather than piling up.
+const WAITING_ROWS: usize = 10_000;
+
+/// Rows arriving from a Source, read on background threads so the app never waits on them
+pub struct Incoming {
+    rows: Receiver<Row>,
+    /// The Unix socket listened on, if any, which is removed when the app is done with it
+    _socket: Option<SocketFile>,
+}
+impl Incoming {
+    /// Start reading from a source. Sockets are bound right away, so a busy address fails here.
+    pub fn open(source: &Source) -> Result<Self> {
+        let (sender, receiver) = channel(WAITING_ROWS);
+        let mut socket = None;
+        match source {
+            Source::Stdin => {
+                std::thread::spawn(move || read_rows(std::io::stdin().lock(), &sender));
+            }
+            #[cfg(unix)]
+            Source::Unix(path) => {
+                // A socket left over from a run that crashed has nobody listening on it anymore
+                if path.exists() && std::os::unix::net::UnixStream::connect(path).is_err() {
+                    std::fs::remove_file(path).with_context(|| format!("Can't remove the old {}", path.display()))?;
+                }
+                let listener = std::os::unix::net::UnixListener::bind(path)
+                    .with_context(|| format!("Can't listen on {}. Is something else already?", path.display()))?;
+                socket = Some(SocketFile(path.clone()));
+                std::thread::spawn(move || {
+                    for stream in listener.incoming().flatten() {
+                        let sender = sender.clone();
+                        std::thread::spawn(move || read_rows(BufReader::new(stream), &sender));
+                    }
+                });
+            }
+            #[cfg(not(unix))]
+            Source::Unix(_) => bail!("Unix sockets aren't available here, try tcp instead"),
+            Source::Tcp(address) => {
+                let listener = TcpListener::bind(address).with_context(|| format!("Can't listen on {}", address))?;
+                std::thread::spawn(move || {
+                    for stream in listener.incoming().flatten() {
+                        let sender = sender.clone();
+                        std::thread::spawn(move || read_rows(BufReader::new(stream), &sender));
+                    }
+                });
+            }
+        }
+        Ok(Incoming {
+            rows: receiver,
+            _socket: socket,
+        })
+    }
+}
+
+/// Removes a Unix socket's file when dropped
+struct SocketFile(PathBuf);
+impl Drop for SocketFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// Send each line as a row until the reader runs dry or nobody's listening anymore.
+/// Bad lines are skipped, so one typo doesn't end the stream, and so are rows that arrive faster
+/// than the app takes them in.
+fn read_rows(reader: impl BufRead, sender: &Sender<Row>) {
+    let mut dropped = 0;
+    for line in reader.lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => return,
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str(&line) {
+            Ok(row) => match sender.try_send(row) {
+                Ok(()) if dropped > 0 => {
+                    warn!("Dropped {} streamed rows that arrived faster than they could be plotted", dropped);
+                    dropped = 0;
+                }
+                Ok(()) => {}
+                Err(TrySendError::Full(_)) => dropped += 1,
+                Err(TrySendError::Closed(_)) => return,
+            },
+            Err(error) => warn!("Skipping a streamed row that isn't a JSON object: {}", error),
+        }
+    }
+}
+
+/// A row that arrived from the stream since the last frame
+pub struct Streamed(pub Row);
+
+/// Turns rows from the Incoming resource into Streamed events, for visuals to take in
+pub struct StreamPlugin;
+impl Plugin for StreamPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<Streamed>()
+            .add_system_to_stage(CoreStage::PreUpdate, receive_rows)
+            .add_system_to_stage(CoreStage::Last, close_on_exit);
+    }
+}
+
+fn receive_rows(mut incoming: ResMut<Incoming>, mut streamed: EventWriter<Streamed>) {
+    while let Ok(row) = incoming.rows.try_recv() {
+        streamed.send(Streamed(row));
+    }
+}
+
+/// Stop listening as the app exits, since the window may end the process before anything is dropped
+fn close_on_exit(mut commands: Commands, mut exits: EventReader<AppExit>) {
+    if exits.iter().next().is_some() {
+        commands.remove_resource::<Incoming>();
+    }
+}
+
+#[test]
+fn test_sources() {
+    assert_eq!("-".parse::<Source>().unwrap(), Source::Stdin);
+    assert_eq!("unix:/tmp/avis.sock".parse::<Source>().unwrap(), Source::Unix("/tmp/avis.sock".into()));
+    assert_eq!("tcp:7878".parse::<Source>().unwrap(), Source::Tcp("127.0.0.1:7878".into()));
+    assert_eq!("7878".parse::<Source>().unwrap(), Source::Tcp("127.0.0.1:7878".into()));
+    assert_eq!("tcp:0.0.0.0:80".parse::<Source>().unwrap(), Source::Tcp("0.0.0.0:80".into()));
+    assert!("carrier-pigeon".parse::<Source>().is_err());
+}
+
+#[test]
+fn test_read_rows() {
+    let (sender, mut receiver) = channel(WAITING_ROWS);
+    read_rows("{\"name\": \"a\", \"x\": 1}\n\nnot json\n{\"name\": \"b\"}\n".as_bytes(), &sender);
+    let names = std::iter::from_fn(|| receiver.try_recv().ok())
+        .map(|row| row["name"].to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(names, vec!["a", "b"]);
+}
+
+#[test]
+fn test_full_stream_drops_rows() {
+    let (sender, mut receiver) = channel(1);
+    read_rows("{\"name\": \"a\"}\n{\"name\": \"b\"}\n".as_bytes(), &sender);
+    assert_eq!(receiver.try_recv().unwrap()["name"].to_string(), "a");
+    assert!(receiver.try_recv().is_err());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_socket_file_is_removed() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("avis.sock");
+    // Left over from a run that crashed
+    drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+    assert!(path.exists());
+    let incoming = Incoming::open(&Source::Unix(path.clone())).unwrap();
+    assert!(path.exists());
+    drop(incoming);
+    assert!(!path.exists());
+}
@@ -0,0 +1,467 @@
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+
+use anyhow::*;
+use bevy::prelude::Color;
+use itertools::Itertools;
+use serde::Deserialize;
+
+use crate::feature::{Band, Pipe};
+use crate::palette::Palette;
+use crate::picking::Tooltip;
+use crate::scatterplot::{Attribute, Attributes, Glyph};
+
+/// One row of a Table, by column name
+pub type Row = HashMap<String, Attribute>;
+
+/// Rows of named columns, as read from a CSV, JSON lines, or JSON file
+#[derive(Debug, Clone, Default)]
+pub struct Table {
+    rows: Vec<Row>,
+    /// Where each row is by its values in the columns upsert() last matched on, so streamed rows
+    /// find the row they replace without a search
+    keys: HashMap<Vec<String>, usize>,
+    keyed_by: Vec<String>,
+}
+
+impl Table {
+    /// Read a table, choosing the format by extension: .csv, .tsv, .jsonl or .ndjson, or .json
+    /// for an array of objects
+    pub fn read(path: &Path) -> Result<Self> {
+        let file = std::fs::File::open(path).with_context(|| format!("Can't open {}", path.display()))?;
+        let extension = path.extension().and_then(|e| e.to_str()).unwrap_or_default();
+        match extension.to_lowercase().as_str() {
+            "csv" => Table::from_csv(file, b','),
+            "tsv" => Table::from_csv(file, b'\t'),
+            "jsonl" | "ndjson" => Table::from_json_lines(BufReader::new(file)),
+            "json" => Ok(Table {
+                rows: serde_json::from_reader(BufReader::new(file))?,
+                ..Default::default()
+            }),
+            _ => bail!("Can't tell what kind of table {} is", path.display()),
+        }
+    }
+
+    /// Read a table with a header row, guessing the type of each value
+    pub fn from_csv(reader: impl Read, delimiter: u8) -> Result<Self> {
+        let mut reader = csv::ReaderBuilder::new().delimiter(delimiter).from_reader(reader);
+        let header = reader.headers()?.clone();
+        let mut rows = vec![];
+        for record in reader.records() {
+            let record = record?;
+            rows.push(
+                header
+                    .iter()
+                    .zip(record.iter())
+                    .map(|(column, value)| (column.to_string(), Attribute::parse(value)))
+                    .collect(),
+            );
+        }
+        Ok(Table {
+            rows,
+            ..Default::default()
+        })
+    }
+
+    /// Read one JSON object per line, skipping blank lines
+    pub fn from_json_lines(reader: impl BufRead) -> Result<Self> {
+        let mut rows = vec![];
+        for (number, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            rows.push(serde_json::from_str(&line).with_context(|| format!("Bad JSON on line {}", number + 1))?);
+        }
+        Ok(Table {
+            rows,
+            ..Default::default()
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    /// Whether any row has this column
+    pub fn has(&self, column: &str) -> bool {
+        self.rows.iter().any(|row| row.contains_key(column))
+    }
+
+    /// Whether a column holds only numbers, ignoring missing values
+    pub fn is_numeric(&self, column: &str) -> bool {
+        self.rows.iter().all(|row| {
+            matches!(row.get(column), Some(Attribute::Number(_)) | Some(Attribute::Missing) | None)
+        })
+    }
+
+    /// A column of numbers. Missing or non-numeric values become NaN, so they aren't plotted.
+    pub fn numbers(&self, column: &str) -> Result<Vec<f32>> {
+        ensure!(self.has(column), "There's no column named {}", column);
+        Ok(self
+            .rows
+            .iter()
+            .map(|row| match row.get(column) {
+                Some(Attribute::Number(n)) => *n,
+                _ => f32::NAN,
+            })
+            .collect())
+    }
+
+    /// A column as text. Missing values become empty strings.
+    pub fn texts(&self, column: &str) -> Result<Vec<String>> {
+        ensure!(self.has(column), "There's no column named {}", column);
+        Ok(self
+            .rows
+            .iter()
+            .map(|row| match row.get(column) {
+                Some(Attribute::Missing) | None => String::new(),
+                Some(value) => value.to_string(),
+            })
+            .collect())
+    }
+
+    /// Color each row by a column: numbers along viridis, and anything else by category
+    pub fn colors(&self, column: &str) -> Result<Vec<Color>> {
+        if self.is_numeric(column) {
+            let values = self.numbers(column)?;
+            Ok(Palette::viridis().scale(Pipe::from(&values[..])).convert(&values))
+        } else {
+            let keys = self.texts(column)?;
+            Ok(Band::from(&keys[..]).colors(&keys, &Palette::category10()))
+        }
+    }
+
+    /// Shape each row by a column: glyph names are used as they are, and anything else by category
+    pub fn glyphs(&self, column: &str) -> Result<Vec<Glyph>> {
+        let keys = self.texts(column)?;
+        let is_named = |key: &String| key.is_empty() || key.parse::<Glyph>().is_ok();
+        if keys.iter().any(|key| !key.is_empty()) && keys.iter().all(is_named) {
+            return Ok(keys.iter().map(|key| key.parse().unwrap_or_default()).collect());
+        }
+        let band = Band::from(&keys[..]);
+        Ok(keys
+            .iter()
+            .map(|key| band.index(key).map_or(Glyph::default(), |i| Glyph::CATEGORIES[i % Glyph::CATEGORIES.len()]))
+            .collect())
+    }
+
+    /// Describe each row, titled by one column, listing these columns first and then the rest by name
+    pub fn tooltips(&self, title: Option<&str>, first: &[&str]) -> Vec<Tooltip> {
+        self.rows
+            .iter()
+            .map(|row| {
+                let text = |column: &str| row.get(column).map_or(String::new(), |value| value.to_string());
+                let mut tooltip = Tooltip::new(title.map(text).unwrap_or_default());
+                for column in first.iter().filter(|column| row.contains_key(**column)) {
+                    tooltip = tooltip.with(*column, text(column));
+                }
+                let rest = row
+                    .keys()
+                    .filter(|column| Some(column.as_str()) != title && !first.contains(&column.as_str()))
+                    .sorted();
+                for column in rest {
+                    tooltip = tooltip.with(column, text(column));
+                }
+                tooltip
+            })
+            .collect()
+    }
+
+    /// Add a row, replacing the earlier row with the same values in these columns, like an older
+    /// reading from the same sensor. With no columns, every row is new.
+    pub fn upsert(&mut self, row: Row, key: &[&str]) {
+        if key.is_empty() {
+            // It's still indexed by the last key, in case rows are matched by it again
+            if let Some(values) = key_of(&row, &self.keyed_by).filter(|_| !self.keyed_by.is_empty()) {
+                self.keys.entry(values).or_insert(self.rows.len());
+            }
+            self.rows.push(row);
+            return;
+        }
+        if self.keyed_by != key {
+            self.keyed_by = key.iter().map(|column| column.to_string()).collect();
+            // The first row with each key is the one that gets replaced
+            self.keys = (self.rows.iter().enumerate().rev())
+                .filter_map(|(index, row)| Some((key_of(row, key)?, index)))
+                .collect();
+        }
+        match key_of(&row, key) {
+            Some(values) => match self.keys.get(&values) {
+                Some(&index) => self.rows[index] = row,
+                None => {
+                    self.keys.insert(values, self.rows.len());
+                    self.rows.push(row);
+                }
+            },
+            None => self.rows.push(row),
+        }
+    }
+
+    /// Just these rows, in this order
+    pub fn select(&self, rows: &[usize]) -> Table {
+        Table {
+            rows: rows.iter().map(|&row| self.rows[row].clone()).collect(),
+            ..Default::default()
+        }
+    }
+
+    /// Regroup a table with a row for each thing at each time into frames, one per date.
+    /// Things are told apart by the id column, and keep their last row until they have a new one.
+    pub fn series(&self, time: &str, id: &str, dates: &[String]) -> Result<Series> {
+        let times = self.texts(time)?;
+        let ids = self.texts(id)?;
+        let things = ids.iter().unique().cloned().collect_vec();
+        let index: HashMap<&String, usize> = things.iter().enumerate().map(|(i, thing)| (thing, i)).collect();
+        let mut rows = vec![vec![None; things.len()]; dates.len()];
+        for (row, (time, id)) in times.iter().zip(&ids).enumerate() {
+            if let Some(frame) = dates.iter().position(|date| date == time) {
+                rows[frame][index[id]] = Some(row);
+            }
+        }
+        for frame in 1..rows.len() {
+            let (before, after) = rows.split_at_mut(frame);
+            for (row, earlier) in after[0].iter_mut().zip(&before[frame - 1]) {
+                if row.is_none() {
+                    *row = *earlier;
+                }
+            }
+        }
+        Ok(Series { ids: things, rows })
+    }
+
+    /// Every column but these, for each row
+    pub fn attributes_except(&self, columns: &[&str]) -> Vec<Attributes> {
+        self.rows
+            .iter()
+            .map(|row| {
+                Attributes(
+                    row.iter()
+                        .filter(|(column, _)| !columns.contains(&column.as_str()))
+                        .map(|(column, value)| (column.clone(), value.clone()))
+                        .collect(),
+                )
+            })
+            .collect_vec()
+    }
+}
+
+/// A row's values in some columns, to match it by. Rows without them all can't be matched with anything.
+fn key_of(row: &Row, columns: &[impl AsRef<str>]) -> Option<Vec<String>> {
+    columns
+        .iter()
+        .map(|column| row.get(column.as_ref()).map(|value| format!("{:?}", value)))
+        .collect()
+}
+
+/// A table regrouped into frames over time, each with a row (or none yet) for every thing
+#[derive(Debug, Clone)]
+pub struct Series {
+    /// Each thing, in the order it first appears
+    pub ids: Vec<String>,
+    /// The row for each thing at each frame, as [frame][thing]
+    rows: Vec<Vec<Option<usize>>>,
+}
+impl Series {
+    /// A single frame with every row of a table, for data that doesn't change
+    pub fn still(table: &Table) -> Self {
+        Series {
+            ids: (0..table.len()).map(|row| row.to_string()).collect(),
+            rows: vec![(0..table.len()).map(Some).collect()],
+        }
+    }
+
+    /// Spread a column (or anything with a value for each row) over the frames
+    pub fn frames<T: Clone>(&self, values: &[T], missing: T) -> Vec<Vec<T>> {
+        self.rows
+            .iter()
+            .map(|frame| {
+                frame
+                    .iter()
+                    .map(|row| row.map_or_else(|| missing.clone(), |row| values[row].clone()))
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// The first row of each thing, e.g. for its name and tooltip
+    pub fn first_rows(&self) -> Vec<usize> {
+        (0..self.ids.len())
+            .map(|thing| self.rows.iter().find_map(|frame| frame[thing]).unwrap_or_default())
+            .collect()
+    }
+}
+
+/// Which columns of a table drive each part of a scatterplot
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Columns {
+    pub lat: String,
+    pub lon: String,
+    /// Height above the surface. Without it, points sit low.
+    pub alt: Option<String>,
+    /// Without it, every point is the same size
+    pub size: Option<String>,
+    /// Numbers get a gradient and text gets a color per category.
+    /// Without it, points are colored by size.
+    pub color: Option<String>,
+    /// A name or id for each point
+    pub label: Option<String>,
+    /// Glyph names, or categories to give each their own glyph. Without it, every point is the same.
+    pub glyph: Option<String>,
+    /// A date or time for each row, to animate points listed once per time.
+    /// Points are then told apart by their label.
+    pub time: Option<String>,
+}
+impl Default for Columns {
+    fn default() -> Self {
+        Columns {
+            lat: "lat".into(),
+            lon: "lon".into(),
+            alt: Some("alt".into()),
+            size: Some("size".into()),
+            color: None,
+            label: Some("name".into()),
+            glyph: None,
+            time: None,
+        }
+    }
+}
+impl Columns {
+    /// Read a column mapping from a JSON object like {"lat": "latitude", "size": "population"}
+    pub fn read(path: &Path) -> Result<Self> {
+        Ok(serde_json::from_slice(&std::fs::read(path)?)?)
+    }
+
+    /// A column of numbers if it's mapped and in the table, or else the same number for every row
+    pub fn numbers_or(&self, table: &Table, column: &Option<String>, otherwise: f32) -> Result<Vec<f32>> {
+        match column {
+            Some(column) if table.has(column) => table.numbers(column),
+            _ => Ok(vec![otherwise; table.len()]),
+        }
+    }
+
+    /// The column of point names, if the table has it. Without a name column, the default label
+    /// falls back to an id column, as the points files have always allowed.
+    pub fn label_in(&self, table: &Table) -> Option<&str> {
+        match self.label.as_deref() {
+            Some("name") if !table.has("name") && table.has("id") => Some("id"),
+            label => label.filter(|column| table.has(column)),
+        }
+    }
+
+    /// The columns that have a role in the plot, and so aren't extra attributes
+    pub fn used(&self) -> Vec<&str> {
+        [
+            Some(&self.lat),
+            Some(&self.lon),
+            self.alt.as_ref(),
+            self.size.as_ref(),
+            self.color.as_ref(),
+            self.label.as_ref(),
+            self.glyph.as_ref(),
+            self.time.as_ref(),
+        ]
+            .into_iter()
+            .flatten()
+            .map(|column| column.as_str())
+            .collect()
+    }
+}
+
+#[test]
+fn test_read_csv() {
+    let csv = "name,lat,lon,pop,capital\nAustin,30.27,-97.74,961855,true\nPlano,33.02,-96.7,,false\n";
+    let table = Table::from_csv(csv.as_bytes(), b',').unwrap();
+    assert_eq!(table.len(), 2);
+    assert_eq!(table.numbers("lat").unwrap(), vec![30.27, 33.02]);
+    assert!(table.numbers("pop").unwrap()[1].is_nan());
+    assert!(table.is_numeric("pop"));
+    assert!(!table.is_numeric("name"));
+    assert_eq!(table.texts("name").unwrap(), vec!["Austin", "Plano"]);
+    assert!(table.numbers("elevation").is_err());
+
+    let extras = table.attributes_except(&Columns::default().used());
+    assert_eq!(extras[0].number("pop"), Some(961855.0));
+    assert_eq!(extras[1].get("capital"), Some(&Attribute::Bool(false)));
+    assert_eq!(extras[0].get("lat"), None);
+
+    let tooltips = table.tooltips(Some("name"), &["lat", "lon"]);
+    assert_eq!(
+        tooltips[0].to_string(),
+        "Austin\nlat: 30.27\nlon: -97.74\ncapital: true\npop: 961855"
+    );
+    let colors = table.colors("name").unwrap();
+    assert_ne!(colors[0], colors[1]);
+    assert_eq!(table.glyphs("capital").unwrap(), vec![Glyph::Sphere, Glyph::Cube]);
+}
+
+#[test]
+fn test_label_falls_back_to_id() {
+    let table = Table::from_csv("id,lat,lon\n01,30.27,-97.74\n".as_bytes(), b',').unwrap();
+    assert_eq!(Columns::default().label_in(&table), Some("id"));
+    assert_eq!(table.texts("id").unwrap(), vec!["01"]);
+    let columns = Columns {
+        label: Some("city".into()),
+        ..Columns::default()
+    };
+    assert_eq!(columns.label_in(&table), None);
+}
+
+#[test]
+fn test_read_json_lines() {
+    let lines = "{\"city\": \"Austin\", \"y\": 30.27}\n\n{\"city\": \"Plano\", \"y\": 33.02, \"x\": -96.7}\n";
+    let table = Table::from_json_lines(lines.as_bytes()).unwrap();
+    assert_eq!(table.len(), 2);
+    let columns: Columns = serde_json::from_str(r#"{"lat": "y", "lon": "x", "label": "city"}"#).unwrap();
+    assert!(table.numbers(&columns.lon).unwrap()[0].is_nan());
+    assert_eq!(columns.numbers_or(&table, &columns.size, 1.0).unwrap(), vec![1.0, 1.0]);
+    assert_eq!(table.texts(columns.label.as_ref().unwrap()).unwrap(), vec!["Austin", "Plano"]);
+    assert!(Table::from_json_lines("{\"city\": ".as_bytes()).is_err());
+}
+
+#[test]
+fn test_series() {
+    let csv = "month,city,pop\n2,Austin,11\n1,Austin,10\n2,Plano,5\n3,Austin,12\n";
+    let table = Table::from_csv(csv.as_bytes(), b',').unwrap();
+    let dates = ["1", "2", "3"].map(String::from);
+    let series = table.series("month", "city", &dates).unwrap();
+    assert_eq!(series.ids, vec!["Austin", "Plano"]);
+    // Plano appears late, and then holds still
+    let pops = series.frames(&table.numbers("pop").unwrap(), f32::NAN);
+    assert_eq!(pops[0][0], 10.0);
+    assert!(pops[0][1].is_nan());
+    assert_eq!(pops[1], vec![11.0, 5.0]);
+    assert_eq!(pops[2], vec![12.0, 5.0]);
+    assert_eq!(series.first_rows(), vec![1, 2]);
+    assert_eq!(Series::still(&table).frames(&[1, 2, 3, 4], 0), vec![vec![1, 2, 3, 4]]);
+}
+
+#[test]
+fn test_upsert() {
+    let mut table = Table::from_csv("name,x\nAustin,1\nPlano,2\n".as_bytes(), b',').unwrap();
+    let row = |name: &str, x: f32| Row::from([("name".into(), Attribute::Text(name.into())), ("x".into(), Attribute::Number(x))]);
+    table.upsert(row("Plano", 3.0), &["name"]);
+    table.upsert(row("Waco", 4.0), &["name"]);
+    assert_eq!(table.texts("name").unwrap(), vec!["Austin", "Plano", "Waco"]);
+    assert_eq!(table.numbers("x").unwrap(), vec![1.0, 3.0, 4.0]);
+    table.upsert(row("Waco", 5.0), &[]);
+    table.upsert(row("Waco", 6.0), &["city"]);
+    assert_eq!(table.len(), 5);
+    // Matching by name again still finds the first row with each name
+    table.upsert(row("Waco", 7.0), &["name"]);
+    table.upsert(row("Austin", 8.0), &["name"]);
+    assert_eq!(table.len(), 5);
+    assert_eq!(table.numbers("x").unwrap(), vec![8.0, 3.0, 7.0, 5.0, 6.0]);
+    // A row added without a key is still found by the key matched on before
+    let mut table = Table::default();
+    table.upsert(row("Austin", 1.0), &["name"]);
+    table.upsert(row("Waco", 2.0), &[]);
+    table.upsert(row("Waco", 3.0), &["name"]);
+    assert_eq!(table.numbers("x").unwrap(), vec![1.0, 3.0]);
+}
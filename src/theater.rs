@@ -1,6 +1,22 @@
 use bevy::prelude::*;
 use std::{f32::consts::PI, ops::RangeInclusive};
 
+use crate::capture::Capture;
+use crate::feature::{Feature, Pipe};
+
+/// One of the three directions of the Theater
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Axis {
+    /// Left to right, along x
+    Width,
+    /// Floor to ceiling, along y
+    Height,
+    /// Back to front, along z
+    Depth,
+}
+
+/// The stage every visual shares, available as a resource once the plugin is added
+#[derive(Debug, Clone)]
 pub struct Theater {
     pub width: RangeInclusive<f32>,
     pub height: RangeInclusive<f32>,
@@ -15,11 +31,46 @@ impl Default for Theater {
         }
     }
 }
+impl Theater {
+    /// The extent of the stage along one axis
+    pub fn range(&self, axis: Axis) -> &RangeInclusive<f32> {
+        match axis {
+            Axis::Width => &self.width,
+            Axis::Height => &self.height,
+            Axis::Depth => &self.depth,
+        }
+    }
+    /// Part of an axis, measured from 0 at its start to 1 at its end
+    pub fn part(&self, axis: Axis, fraction: RangeInclusive<f32>) -> RangeInclusive<f32> {
+        let range = self.range(axis);
+        let at = |f: f32| range.start() + (range.end() - range.start()) * f;
+        at(*fraction.start())..=at(*fraction.end())
+    }
+    /// Stretch a feature bound to an axis to fill it. Unbound features are left as they are.
+    pub fn fit(&self, feature: &Feature) -> Feature {
+        Feature {
+            pipe: self.pipe_for(feature),
+            ..feature.clone()
+        }
+    }
+    /// The pipe fit() would give a feature, without copying its content
+    pub fn pipe_for(&self, feature: &Feature) -> Pipe {
+        match feature.axis {
+            Some(axis) => feature.pipe.clone().fit_to(self.range(axis)),
+            None => feature.pipe.clone(),
+        }
+    }
+}
+
 impl Plugin for Theater {
     fn build(&self, app: &mut App) {
-        app.insert_resource(bevy_atmosphere::AtmosphereMat::default())
+        // A Capture renders without a window, so it brings its own plugins
+        if !app.world.contains_resource::<Capture>() {
+            app.add_plugins(DefaultPlugins);
+        }
+        app.insert_resource(self.clone())
+            .insert_resource(bevy_atmosphere::AtmosphereMat::default())
             .insert_resource(Msaa { samples: 4 })
-            .add_plugins(DefaultPlugins)
             .add_plugin(bevy_atmosphere::AtmospherePlugin { dynamic: false, sky_radius: 100.0 })
             .add_plugin(bevy_fly_camera::FlyCameraPlugin)
             .add_startup_system(setup_world);
@@ -69,3 +120,13 @@ fn setup_world(
         })
         .insert(bevy_fly_camera::FlyCamera::default());
 }
+
+#[test]
+fn test_theater_fit() {
+    let theater = Theater::default();
+    assert_eq!(theater.part(Axis::Height, 0.1..=0.5), 0.5..=2.5);
+    let feature = Pipe::from(&[10.0, 20.0][..]).bundle(vec![10.0, 20.0]);
+    // Unbound features keep their own pipes
+    assert_eq!(theater.fit(&feature).convert(), vec![10.0, 20.0]);
+    assert_eq!(theater.fit(&feature.on(Axis::Width)).convert(), vec![-5.0, 5.0]);
+}
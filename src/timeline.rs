@@ -0,0 +1,198 @@
+use bevy::prelude::*;
+use itertools::Itertools;
+
+use crate::clock::{Clock, ClockPlugin};
+use crate::overlay::{OverlayPlugin, OVERLAY_FONT};
+
+/// Playback through a series of dated frames, shared by everything that changes over time.
+///
+/// P plays and pauses, the left and right arrows step between frames (and scrub when held),
+/// Home and End jump to either end, and the brackets slow down and speed up playback.
+#[derive(Debug, Clone)]
+pub struct Timeline {
+    /// The name of each frame, in order, e.g. "2021-03"
+    pub dates: Vec<String>,
+    /// The current frame. Between whole frames, animated things are blended.
+    pub frame: f32,
+    pub playing: bool,
+    /// How fast to play, in frames per second
+    pub speed: f32,
+    /// Start over after the last frame, rather than stopping there
+    pub looping: bool,
+}
+
+impl Timeline {
+    /// A timeline with a frame for each distinct date, in order: by number if they're all
+    /// numbers, and otherwise as text, which suits ISO dates like 2021-03-01
+    pub fn new(dates: impl IntoIterator<Item = String>) -> Self {
+        let mut dates = dates.into_iter().filter(|date| !date.is_empty()).unique().collect_vec();
+        match dates.iter().map(|date| date.parse::<f64>().ok()).collect::<Option<Vec<_>>>() {
+            Some(numbers) => {
+                dates = dates
+                    .into_iter()
+                    .zip(numbers)
+                    .sorted_by(|a, b| a.1.total_cmp(&b.1))
+                    .map(|(date, _)| date)
+                    .collect()
+            }
+            None => dates.sort(),
+        }
+        Timeline {
+            dates,
+            frame: 0.0,
+            playing: true,
+            speed: 2.0,
+            looping: true,
+        }
+    }
+
+    /// The last whole frame
+    pub fn last(&self) -> f32 {
+        self.dates.len().saturating_sub(1) as f32
+    }
+
+    /// Where a date falls on the timeline, if it's on it
+    pub fn position(&self, date: &str) -> Option<usize> {
+        self.dates.iter().position(|d| d == date)
+    }
+
+    /// The date of the nearest whole frame
+    pub fn date(&self) -> &str {
+        self.dates
+            .get(self.frame.round() as usize)
+            .map_or("", |date| date.as_str())
+    }
+
+    /// Move to another frame, staying on the timeline
+    pub fn seek(&mut self, frame: f32) {
+        self.frame = frame.clamp(0.0, self.last());
+    }
+
+    /// Play forward some frames, then start over or stop at the end
+    pub fn advance(&mut self, frames: f32) {
+        let frame = self.frame + frames;
+        if frame <= self.last() {
+            self.frame = frame;
+        } else if self.looping && self.last() > 0.0 {
+            self.frame = frame % (self.last() + 1.0);
+            // The jump back to the start shouldn't blend through every frame in between
+            if self.frame > self.last() {
+                self.frame = 0.0;
+            }
+        } else {
+            self.frame = self.last();
+            self.playing = false;
+        }
+    }
+}
+
+impl Plugin for Timeline {
+    fn build(&self, app: &mut App) {
+        app.add_plugin(OverlayPlugin)
+            .add_plugin(ClockPlugin)
+            .insert_resource(self.clone())
+            .add_startup_system(setup_date)
+            .add_system(control_playback)
+            .add_system(show_date.after(control_playback));
+    }
+}
+
+/// The on-screen text showing the Timeline's date
+#[derive(Component)]
+struct DateText;
+
+fn setup_date(mut commands: Commands) {
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Px(16.0),
+                    top: Val::Px(12.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            text: Text::with_section(
+                "",
+                TextStyle {
+                    font: OVERLAY_FONT.typed(),
+                    font_size: 32.0,
+                    color: Color::WHITE,
+                },
+                Default::default(),
+            ),
+            ..Default::default()
+        })
+        .insert(DateText);
+}
+
+/// Play, pause and scrub from the keyboard
+fn control_playback(keys: Res<Input<KeyCode>>, clock: Res<Clock>, mut timeline: ResMut<Timeline>) {
+    if keys.just_pressed(KeyCode::P) {
+        timeline.playing = !timeline.playing;
+    }
+    if keys.just_pressed(KeyCode::Home) {
+        timeline.seek(0.0);
+    }
+    if keys.just_pressed(KeyCode::End) {
+        let last = timeline.last();
+        timeline.seek(last);
+    }
+    if keys.just_pressed(KeyCode::LBracket) {
+        timeline.speed /= 2.0;
+    }
+    if keys.just_pressed(KeyCode::RBracket) {
+        timeline.speed *= 2.0;
+    }
+    // A tap steps to the next whole frame, and holding the key keeps going
+    for (key, direction) in [(KeyCode::Left, -1.0), (KeyCode::Right, 1.0)] {
+        if keys.just_pressed(key) {
+            timeline.playing = false;
+            let frame = if direction > 0.0 {
+                timeline.frame.floor() + 1.0
+            } else {
+                timeline.frame.ceil() - 1.0
+            };
+            timeline.seek(frame);
+        } else if keys.pressed(key) {
+            let frame = timeline.frame + direction * timeline.speed * 2.0 * clock.delta_seconds();
+            timeline.seek(frame);
+        }
+    }
+    // Only touch the timeline while playing, so animations can skip still frames
+    if timeline.playing {
+        let frames = timeline.speed * clock.delta_seconds();
+        timeline.advance(frames);
+    }
+}
+
+fn show_date(timeline: Res<Timeline>, mut text: Query<&mut Text, With<DateText>>) {
+    if !timeline.is_changed() {
+        return;
+    }
+    for mut text in text.iter_mut() {
+        let state = if timeline.playing { "" } else { "  (paused)" };
+        text.sections[0].value = format!("{}{}", timeline.date(), state);
+    }
+}
+
+#[test]
+fn test_timeline() {
+    let mut timeline = Timeline::new(["10", "9", "", "10", "100"].map(String::from));
+    assert_eq!(timeline.dates, vec!["9", "10", "100"]);
+    assert_eq!(timeline.position("100"), Some(2));
+    timeline.advance(1.4);
+    assert_eq!(timeline.date(), "10");
+    // Looping goes straight from the last frame back to the first
+    timeline.advance(1.0);
+    assert_eq!(timeline.frame, 0.0);
+    timeline.advance(3.4);
+    assert!((timeline.frame - 0.4).abs() < 1e-5);
+    timeline.looping = false;
+    timeline.advance(5.0);
+    assert_eq!((timeline.frame, timeline.playing), (2.0, false));
+
+    let timeline = Timeline::new(["2021-03", "2020-12", "2021-01"].map(String::from));
+    assert_eq!(timeline.dates, vec!["2020-12", "2021-01", "2021-03"]);
+}
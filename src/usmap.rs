@@ -1,19 +1,29 @@
-use std::collections::BTreeMap;
+use std::collections::HashMap;
 use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
 
 use anyhow::*;
 use bevy::prelude::*;
 use geo::map_coords::TryMapCoords;
 use itertools::Itertools;
 
-use crate::feature::{Overflow, Pipe};
-use crate::meshutil::estimate_vertex_normals;
-use bevy::render::mesh::Indices;
-use bevy::render::render_resource::PrimitiveTopology;
+use crate::feature::Pipe;
+use crate::labels::Label;
+use crate::meshutil::{extrude_polygon, outline_rings, signed_area};
+use crate::palette::{ColorScale, Palette};
+use crate::picking::Tooltip;
+use crate::projection::Surface;
+use crate::simplify::simplify_shared;
+use crate::timeline::Timeline;
 
+/// One region of a map layer. Usually a state, but it could be a county or a country, too.
 #[derive(Component)]
 pub struct State {
     name: String,
+    /// An identifier like a FIPS code, if the layer has one
+    id: Option<String>,
+    /// Numeric properties bound from the layer
+    properties: HashMap<String, f32>,
     polygon: geo::Polygon<f32>,
 }
 impl State {
@@ -37,37 +47,89 @@ impl State {
         Color::rgb(color[0], color[1], color[2])
     }
 }
-pub struct USMapPlugin;
 
-#[derive(Component)]
-pub struct USMap {
-    lat_pipe: Pipe,
-    lon_pipe: Pipe,
-    loaded: bool,
+/// A GeoJSON file of regions, and which of their properties to read
+#[derive(Debug, Clone)]
+pub struct GeoLayer {
+    path: PathBuf,
+    name_key: String,
+    id_key: Option<String>,
+    /// Numeric properties to keep on each region
+    bindings: Vec<String>,
 }
-impl USMap {
-    pub fn new(lon_pipe: Pipe, lat_pipe: Pipe) -> Result<Self> {
-        Ok(USMap {
-            lat_pipe,
-            lon_pipe,
-            loaded: false,
-        })
+/// The US states from the us-maps submodule
+impl Default for GeoLayer {
+    fn default() -> Self {
+        GeoLayer::new("us-maps/geojson/state.geo.json")
+            .name_key("NAME10")
+            .id_key("STATE10")
+    }
+}
+impl GeoLayer {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        GeoLayer {
+            path: path.into(),
+            name_key: "NAME".into(),
+            id_key: None,
+            bindings: vec![],
+        }
+    }
+    /// The property holding each region's name
+    pub fn name_key(mut self, key: &str) -> Self {
+        self.name_key = key.into();
+        self
+    }
+    /// The property holding each region's identifier, like a FIPS code
+    pub fn id_key(mut self, key: &str) -> Self {
+        self.id_key = Some(key.into());
+        self
+    }
+    /// Keep this numeric property on each region, e.g. to drive a choropleth
+    pub fn bind(mut self, key: &str) -> Self {
+        if !self.bindings.iter().any(|k| k == key) {
+            self.bindings.push(key.into());
+        }
+        self
     }
 
-    fn read_counties(&self) -> Result<Vec<State>> {
-        // This JSON is invalid UTF8, unfortunately, so we fix it on the fly, which uses more memory.
-        let shapes = std::fs::read("us-maps/geojson/state.geo.json")?;
+    /// Read every polygon in the layer. Regions made of several polygons become several States.
+    pub fn read(&self) -> Result<Vec<State>> {
+        // Some of these files are invalid UTF8, unfortunately, so we fix it on the fly, which uses more memory.
+        let shapes = std::fs::read(&self.path)
+            .with_context(|| format!("Couldn't read map layer {}", self.path.display()))?;
         let shapes = String::from_utf8_lossy(&shapes)
             .into_owned()
             .parse::<geojson::GeoJson>()?;
-        let mut counties = vec![];
+        let mut regions = vec![];
         for feature in geojson::FeatureCollection::try_from(shapes)?.features {
             let name = feature
-                .property("NAME10")
-                .ok_or(anyhow!("State missing name"))?
+                .property(&self.name_key)
+                .ok_or_else(|| anyhow!("Region missing name property {}", self.name_key))?
                 .as_str()
-                .ok_or(anyhow!("State name is not a string"))?
+                .ok_or_else(|| anyhow!("Region name {} is not a string", self.name_key))?
                 .to_string();
+            // Identifiers are often numbers, but they're still just labels
+            let id = self
+                .id_key
+                .as_ref()
+                .and_then(|key| feature.property(key))
+                .and_then(|id| match id {
+                    serde_json::Value::String(id) => Some(id.clone()),
+                    serde_json::Value::Number(id) => Some(id.to_string()),
+                    _ => None,
+                });
+            let properties = self
+                .bindings
+                .iter()
+                .filter_map(|key| {
+                    let value = match feature.property(key)? {
+                        serde_json::Value::Number(n) => n.as_f64()? as f32,
+                        serde_json::Value::String(s) => s.trim().parse().ok()?,
+                        _ => return None,
+                    };
+                    Some((key.clone(), value))
+                })
+                .collect::<HashMap<_, _>>();
             if feature.geometry.is_none() {
                 continue;
             }
@@ -78,19 +140,339 @@ impl USMap {
                 _ => vec![],
             };
             for polygon in polygons {
-                counties.push(State {
+                regions.push(State {
                     name: name.clone(),
+                    id: id.clone(),
+                    properties: properties.clone(),
                     polygon,
                 })
             }
         }
-        Ok(counties)
+        Ok(regions)
+    }
+}
+
+pub struct USMapPlugin;
+
+/// How far past the distance where the level of detail changes the camera has to go before it
+/// does, as a fraction of that distance, so a camera resting near it doesn't flicker between levels
+const DETAIL_HYSTERESIS: f32 = 0.1;
+
+#[derive(Component)]
+pub struct USMap {
+    surface: Surface,
+    loaded: bool,
+    layer: GeoLayer,
+    /// Data for a choropleth, keyed by region name or id
+    values: HashMap<String, f32>,
+    /// Choropleth values at each frame of a Timeline, if they change over time. The first is `values`.
+    frames: Vec<HashMap<String, f32>>,
+    /// A bound property of the layer to use for the choropleth, if it isn't in values
+    value_key: Option<String>,
+    fill: Option<ColorScale>,
+    extrude: Option<Pipe>,
+    /// Fit the extrusion to the values once they're loaded
+    extrude_range: Option<RangeInclusive<f32>>,
+    /// Levels of detail, as (furthest camera distance, simplification tolerance in degrees)
+    detail: Vec<(f32, f32)>,
+    /// Which level of detail is showing
+    level: usize,
+    /// The corners of a box around the whole map, once it's built
+    bounds: Option<(Vec3, Vec3)>,
+    /// The width and color of the outlines around each region
+    borders: Option<(f32, Color)>,
+    /// How tall to write each region's name, if at all
+    labels: Option<f32>,
+}
+
+/// The outline of one region, drawn over its fill
+#[derive(Component)]
+pub struct Border;
+
+/// How high a region stood when it was built, so its label can follow it up and down
+#[derive(Component)]
+struct Altitude(f32);
+
+/// The label of a region, which rises and falls with it
+#[derive(Component)]
+struct RegionLabel {
+    region: Entity,
+    /// Where the label was when the region was built
+    anchor: Vec3,
+}
+
+/// The meshes of one region, from most to least detailed, matching USMap's levels of detail
+#[derive(Component)]
+pub struct Detail {
+    meshes: Vec<Handle<Mesh>>,
+}
+impl USMap {
+    /// Draw the map on a surface: usually a MapFrame on the floor, or a globe
+    pub fn new(surface: impl Into<Surface>) -> Result<Self> {
+        Ok(USMap {
+            surface: surface.into(),
+            loaded: false,
+            layer: GeoLayer::default(),
+            values: HashMap::new(),
+            frames: vec![],
+            value_key: None,
+            fill: None,
+            extrude: None,
+            extrude_range: None,
+            detail: vec![(4.0, 0.0), (10.0, 0.02), (f32::INFINITY, 0.1)],
+            level: 0,
+            bounds: None,
+            borders: Some((0.01, Color::rgb(0.1, 0.1, 0.1))),
+            labels: None,
+        })
+    }
+
+    /// Show a different set of regions, instead of the US states
+    pub fn layer(mut self, layer: GeoLayer) -> Self {
+        self.layer = layer;
+        self
+    }
+
+    /// Color each region by a value, keyed by region name or id (e.g. FIPS code).
+    /// Colors come from viridis unless you choose another scale with fill()
+    pub fn choropleth(mut self, values: HashMap<String, f32>) -> Self {
+        self.values = values;
+        self
+    }
+
+    /// Color each region by values that change over time, one set for each frame of a Timeline
+    pub fn choropleth_frames(mut self, frames: Vec<HashMap<String, f32>>) -> Self {
+        if let Some(first) = frames.first() {
+            self.values = first.clone();
+        }
+        self.frames = frames;
+        self
+    }
+
+    /// Swap in new choropleth frames, as when the Timeline gains dates. The scales stay as they were.
+    pub fn set_choropleth_frames(&mut self, frames: Vec<HashMap<String, f32>>) {
+        if let Some(first) = frames.first() {
+            self.values = first.clone();
+        }
+        self.frames = frames;
+    }
+
+    /// Color each region by one of its own numeric properties from the layer
+    pub fn choropleth_property(mut self, key: &str) -> Self {
+        self.layer = self.layer.bind(key);
+        self.value_key = Some(key.into());
+        self
+    }
+
+    /// Choose how choropleth values become colors
+    pub fn fill(mut self, scale: ColorScale) -> Self {
+        self.fill = Some(scale);
+        self
+    }
+
+    /// Raise each region by its choropleth value, through this pipe
+    pub fn extrude(mut self, pipe: Pipe) -> Self {
+        self.extrude = Some(pipe);
+        self
+    }
+
+    /// Raise each region by its choropleth value, fit to this range of heights
+    pub fn extrude_to(mut self, range: RangeInclusive<f32>) -> Self {
+        self.extrude_range = Some(range);
+        self
+    }
+
+    /// Outline each region with a ribbon this wide, in the Theater's units, so neighbors with
+    /// similar colors stay distinct. A width of 0 leaves the borders out.
+    pub fn borders(mut self, width: f32, color: Color) -> Self {
+        self.borders = (width > 0.0).then_some((width, color));
+        self
+    }
+
+    /// Write each region's name over it, with letters this tall. Needs the LabelPlugin.
+    pub fn labels(mut self, height: f32) -> Self {
+        self.labels = Some(height);
+        self
+    }
+
+    /// Simplify the regions when the camera is far away, as (furthest camera distance, tolerance)
+    /// pairs from nearest to furthest. Tolerances are in degrees, and 0 keeps every point.
+    /// The whole map switches at once, so borders between neighbors stay watertight.
+    pub fn detail(mut self, levels: Vec<(f32, f32)>) -> Self {
+        if !levels.is_empty() {
+            self.detail = levels;
+        }
+        self
+    }
+
+    /// Choose a level of detail for a camera this far from the map
+    fn level_at(&self, distance: f32) -> usize {
+        self.detail
+            .iter()
+            .position(|(furthest, _)| distance <= *furthest)
+            .unwrap_or(self.detail.len() - 1)
+    }
+
+    /// Choose a level of detail for a camera this far from the map, staying on the current level
+    /// until the camera is clearly past the distance where it changes
+    fn level_near(&self, distance: f32) -> usize {
+        let coarser = self.level_at(distance * (1.0 - DETAIL_HYSTERESIS));
+        let finer = self.level_at(distance * (1.0 + DETAIL_HYSTERESIS));
+        if coarser > self.level {
+            coarser
+        } else if finer < self.level {
+            finer
+        } else {
+            self.level
+        }
+    }
+
+    /// Read choropleth values from a JSON object like {"Ohio": 1.5, "49": 2.0}
+    pub fn read_values(path: &Path) -> Result<HashMap<String, f32>> {
+        Ok(serde_json::from_slice(&std::fs::read(path)?)?)
+    }
+
+    /// Read choropleth values that may change over time, like {"2020": {"Ohio": 1.5}, "2021": {..}}.
+    /// Values without dates, as read_values takes them, come back under the empty date.
+    pub fn read_dated_values(path: &Path) -> Result<HashMap<String, HashMap<String, f32>>> {
+        let json: serde_json::Value = serde_json::from_slice(&std::fs::read(path)?)?;
+        match json.as_object() {
+            Some(dates) if !dates.is_empty() && dates.values().all(|values| values.is_object()) => {
+                Ok(serde_json::from_value(json)?)
+            }
+            _ => Ok(HashMap::from([(String::new(), serde_json::from_value(json)?)])),
+        }
+    }
+
+    /// Find the choropleth value for a region at a fractional frame of a Timeline:
+    /// by name, then by id, then from its properties
+    fn value_at(&self, state: &State, frame: f32) -> Option<f32> {
+        let lookup = |values: &HashMap<String, f32>| {
+            values
+                .get(&state.name)
+                .or_else(|| state.id.as_ref().and_then(|id| values.get(id)))
+                .copied()
+        };
+        let value = match self.frames.len() {
+            0 | 1 => lookup(&self.values),
+            count => {
+                // Blend between the frames on either side, or take the nearer if one is missing
+                let frame = frame.clamp(0.0, (count - 1) as f32);
+                let t = frame.fract();
+                match (lookup(&self.frames[frame.floor() as usize]), lookup(&self.frames[frame.ceil() as usize])) {
+                    (Some(a), Some(b)) => Some(a + (b - a) * t),
+                    (a, b) => match t < 0.5 {
+                        true => a,
+                        false => b,
+                    },
+                }
+            }
+        };
+        value.or_else(|| self.value_key.as_ref().and_then(|key| state.properties.get(key).copied()))
+    }
+
+    /// Project a region onto the floor, or None if any of it can't be shown.
+    /// Globes keep longitude and latitude, and wrap the polygon in place() instead.
+    fn flatten(&self, polygon: &geo::Polygon<f32>) -> Option<geo::Polygon<f32>> {
+        let frame = match &self.surface {
+            Surface::Flat(frame) => frame,
+            Surface::Globe { .. } => return Some(polygon.clone()),
+        };
+        let project = |ring: &geo::LineString<f32>| {
+            ring.points()
+                .map(|p| frame.apply(p.x(), p.y()).map(|p| (p.x, p.y)))
+                .collect::<Option<Vec<_>>>()
+                .map(geo::LineString::from)
+        };
+        let holes = polygon.interiors().iter().map(project).collect::<Option<Vec<_>>>()?;
+        Some(geo::Polygon::new(project(polygon.exterior())?, holes))
+    }
+
+    /// Put a point from flatten() at a height in the Theater, if it can be shown there.
+    /// On a globe, each triangle is a flat chord, so very large regions dip slightly below the surface.
+    fn place(&self, p: Vec2, height: f32) -> Option<Vec3> {
+        match &self.surface {
+            Surface::Flat(_) => Some(Vec3::new(p.x, height, p.y)),
+            globe => globe.place(p.x, p.y, height),
+        }
+    }
+
+    /// Now that the regions are loaded, choose default scales for their values
+    fn fit_to_values(&mut self, regions: &[State]) {
+        // Every frame shares the same scales, so colors mean the same thing throughout
+        let map = &*self;
+        let content = (0..map.frames.len().max(1))
+            .flat_map(|frame| regions.iter().filter_map(move |r| map.value_at(r, frame as f32)))
+            .collect_vec();
+        if content.is_empty() {
+            return;
+        }
+        if self.fill.is_none() {
+            self.fill = Some(Palette::viridis().scale(Pipe::from(&content[..])));
+        }
+        if let (None, Some(range)) = (&self.extrude, &self.extrude_range) {
+            self.extrude = Some(Pipe::from(&content[..]).fit_to(range));
+        }
+    }
+
+    /// Describe a region for its tooltip: its name, id, value and bound properties
+    fn tooltip_of(&self, state: &State, frame: f32) -> Tooltip {
+        let mut tooltip = Tooltip::new(&state.name);
+        if let Some(id) = &state.id {
+            tooltip = tooltip.with("id", id);
+        }
+        if let Some(value) = self.value_at(state, frame) {
+            tooltip = tooltip.with("value", value);
+        }
+        for (key, value) in state.properties.iter().sorted_by(|a, b| a.0.cmp(b.0)) {
+            tooltip = tooltip.with(key, value);
+        }
+        tooltip
+    }
+
+    /// The color of a state: from its value if this is a choropleth, otherwise from its name
+    fn color_of(&self, state: &State, frame: f32) -> Color {
+        match &self.fill {
+            Some(scale) => scale.apply(self.value_at(state, frame).unwrap_or(f32::NAN)),
+            None => state.color(),
+        }
+    }
+
+    /// The altitude of a state: from its value if extruded, otherwise a little random jitter
+    fn altitude_of(&self, state: &State, frame: f32) -> f32 {
+        match &self.extrude {
+            Some(pipe) => self.value_at(state, frame).map_or(0.0, |v| pipe.apply(v)),
+            None => rand::random::<f32>() / 50.0,
+        }
+    }
+
+    /// How tall to build solids that are stretched to their altitude, so one that starts out flat can
+    /// still rise. None if the solids are built as tall as they stand.
+    fn stretched_height(&self) -> Option<f32> {
+        match (&self.extrude, &self.surface) {
+            // Solids on a globe rise outward rather than up, so they can't simply be stretched
+            (Some(pipe), Surface::Flat(_)) => Some(*pipe.range().end()).filter(|top| *top > 0.0),
+            _ => None,
+        }
+    }
+
+    /// How far to lift outlines above their regions, so they don't flicker against the fill
+    fn border_lift(&self) -> f32 {
+        self.borders.map_or(0.0, |(width, _)| width / 4.0)
     }
 }
 
+/// How much to stretch a solid built to one height, so it stands at an altitude.
+/// Flattening it completely would leave it without usable normals.
+fn stretch(altitude: f32, built: f32) -> f32 {
+    (altitude / built).max(0.01)
+}
+
 impl Plugin for USMapPlugin {
     fn build(&self, app: &mut App) {
-        app.add_system(setup_counties);
+        app.add_system(setup_regions)
+            .add_system(switch_detail)
+            .add_system(animate_regions.after(setup_regions));
     }
 }
 
@@ -165,75 +547,307 @@ fn test_is_convex() {
     assert_eq!(each_is_convex(&points), vec![true, true, true, false]);
 }
 
-/// Create a vector of triangles from a vector of points on the surface of a polygon.
-/// The polygon may not be convex. Use ear clipping to create a convex polygon.
-fn triangles_from_polygon(polygon: &geo::Polygon<f32>) -> Vec<[usize; 3]> {
-    let mut points = polygon.exterior().points().collect_vec();
-    points.pop(); // Remove the last point, which is a duplicate of the first.
+/// The rings of a polygon as flat points, outline first and then any holes,
+/// each without the repeated closing point. triangles_from_polygon indexes into these, in order.
+pub fn polygon_rings(polygon: &geo::Polygon<f32>) -> Vec<Vec<Vec2>> {
+    std::iter::once(polygon.exterior())
+        .chain(polygon.interiors())
+        .map(|ring| {
+            let mut points = ring.points().map(|p| Vec2::new(p.x(), p.y())).collect_vec();
+            if points.len() > 1 && points.first() == points.last() {
+                points.pop();
+            }
+            points
+        })
+        .collect()
+}
 
-    // If there are not at least three points, bail.
-    if points.len() < 3 {
-        return vec![];
+/// Twice the signed area of triangle ABC: positive when it turns counter-clockwise
+fn turn(a: Vec2, b: Vec2, c: Vec2) -> f32 {
+    (b - a).perp_dot(c - a)
+}
+
+/// Whether P is inside triangle ABC or on its edges, regardless of the triangle's winding
+fn point_in_triangle(p: Vec2, a: Vec2, b: Vec2, c: Vec2) -> bool {
+    let sides = [turn(a, b, p), turn(b, c, p), turn(c, a, p)];
+    !(sides.iter().any(|&s| s < 0.0) && sides.iter().any(|&s| s > 0.0))
+}
+
+/// Whether the direction from vertex V toward T points into a counter-clockwise polygon,
+/// given V's neighbors U (before) and W (after)
+fn locally_inside(u: Vec2, v: Vec2, w: Vec2, t: Vec2) -> bool {
+    if turn(u, v, w) > 0.0 {
+        turn(v, w, t) >= 0.0 && turn(v, t, u) >= 0.0
+    } else {
+        turn(v, w, t) >= 0.0 || turn(v, t, u) >= 0.0
     }
+}
 
-    // For each point in the polygon, find whether this angle is convex or concave.
-    // Use a b-tree so that we can efficiently find the next point and still delete points in the middle.
-    let mut is_convex = each_is_convex(&points).into_iter().enumerate().collect::<BTreeMap<_, _>>();
+/// Find a vertex of the outline that can be connected to a hole's leftmost point with a straight
+/// bridge, without crossing any edges. Returns the position within the outline.
+fn find_bridge(points: &[Vec2], outline: &[usize], hole: Vec2) -> Option<usize> {
+    let n = outline.len();
+    let at = |i: usize| points[outline[i % n]];
+    // Cast a ray left from the hole and find the closest edge it hits
+    let mut hit: Option<(f32, usize)> = None;
+    for i in 0..n {
+        let (a, b) = (at(i), at(i + 1));
+        if a.y == b.y || (a.y - hole.y) * (b.y - hole.y) > 0.0 {
+            continue;
+        }
+        let x = a.x + (hole.y - a.y) * (b.x - a.x) / (b.y - a.y);
+        if x <= hole.x && hit.is_none_or(|(best, _)| x > best) {
+            // Start from whichever end of the edge is further left
+            hit = Some((x, if a.x < b.x { i } else { (i + 1) % n }));
+        }
+    }
+    let (x, mut bridge) = hit?;
+    if x == hole.x {
+        return Some(bridge);
+    }
+    // Other vertices may hide that end of the edge from the hole. If so, the one nearest to the ray
+    // (by angle) is visible instead.
+    let crossing = Vec2::new(x, hole.y);
+    let end = at(bridge);
+    let mut best_tangent = f32::INFINITY;
+    for i in 0..n {
+        let p = at(i);
+        if p.x > hole.x || p.x < end.x || p.x == hole.x || !point_in_triangle(p, hole, crossing, end) {
+            continue;
+        }
+        let tangent = (hole.y - p.y).abs() / (hole.x - p.x);
+        let better = tangent < best_tangent || (tangent == best_tangent && p.x > at(bridge).x);
+        if better && locally_inside(at(i + n - 1), p, at(i + 1), hole) {
+            bridge = i;
+            best_tangent = tangent;
+        }
+    }
+    Some(bridge)
+}
 
-    // Print out how many points are convex and non convex, for debugging
-    println!(
-        "{} convex points before ear clipping",
-        is_convex.values().filter(|&x| *x).count()
-    );
-    println!(
-        "{} non-convex points before ear clipping",
-        is_convex.values().filter(|&x| !*x).count()
-    );
+/// Join the holes into the outline with pairs of bridge edges, so the result is one (weakly) simple
+/// polygon. The outline should run counter-clockwise and the holes clockwise.
+fn bridge_holes(points: &[Vec2], mut outline: Vec<usize>, mut holes: Vec<Vec<usize>>) -> Vec<usize> {
+    // Start each hole at its leftmost point, and handle the holes from left to right, so that every
+    // bridge can only run into the outline or holes already bridged
+    let leftmost = |hole: &[usize]| {
+        (0..hole.len())
+            .min_by(|&a, &b| {
+                let (a, b) = (points[hole[a]], points[hole[b]]);
+                a.x.total_cmp(&b.x).then(a.y.total_cmp(&b.y))
+            })
+            .unwrap_or(0)
+    };
+    for hole in holes.iter_mut() {
+        let start = leftmost(hole);
+        hole.rotate_left(start);
+    }
+    holes.sort_by(|a, b| points[a[0]].x.total_cmp(&points[b[0]].x));
 
-    //
-    // Look for ears in the polygon, and create triangles from them.
-    //
-    let mut triangles = vec![];
+    for hole in holes {
+        let bridge = match find_bridge(points, &outline, points[hole[0]]) {
+            Some(bridge) => bridge,
+            // A hole that is not inside the outline at all can't be bridged
+            None => continue,
+        };
+        // Walk out along the bridge, around the hole, and back again
+        let mut spliced = hole.clone();
+        spliced.push(hole[0]);
+        spliced.push(outline[bridge]);
+        outline.splice(bridge + 1..bridge + 1, spliced);
+    }
+    outline
+}
 
-    // search for (convex, convex, non-convex) angles to indicate an ear.
-    // Then delete the ear by saving triangle ABC and deleting B, then recalculate the convexity of the angle ACD.
-    let mut vertices_since_ear = 0;
-    let mut i = 0;
-    while is_convex.len() > 3  && vertices_since_ear < points.len() {
-        let v = 
-            (is_convex.range(i..))
-            .chain(is_convex.range(0..i)).take(4)
-            .map(|(x, y)| (*x, *y))
-            .collect_vec();
-        if v[0].1 && v[1].1 && !v[2].1 {
-            // If the angle is convex, then we have an ear.
-            // Save the triangle ABC and delete B.
-            triangles.push([v[0].0, v[1].0, v[2].0]);
-            is_convex.remove(&v[1].0);
-            // Determine the new convexity of the angle ACD.
-            let new_convex = points[v[2].0].cross_prod(points[v[0].0], points[v[3].0]) < 0.0;
-            is_convex.insert(v[2].0, new_convex);
-            vertices_since_ear = 0;
-            // No change to i because we just deleted a point.
+/// A coarse spatial index over some vertices, so we can quickly find the ones near a triangle
+struct PointGrid {
+    origin: Vec2,
+    cell_size: f32,
+    cells: HashMap<(i32, i32), Vec<usize>>,
+    members: Vec<usize>,
+}
+impl PointGrid {
+    fn new(points: impl Fn(usize) -> Vec2, members: Vec<usize>) -> Self {
+        let (low, high) = members.iter().fold(
+            (Vec2::splat(f32::INFINITY), Vec2::splat(f32::NEG_INFINITY)),
+            |(low, high), &m| (low.min(points(m)), high.max(points(m))),
+        );
+        // Aim for about one member per cell
+        let span = (high - low).max_element();
+        let cell_size = if span > 0.0 {
+            span / (members.len() as f32).sqrt().max(1.0)
         } else {
-            // Advance to the next point.
-            i = v[1].0;
+            1.0
+        };
+        let mut grid = PointGrid {
+            origin: low,
+            cell_size,
+            cells: HashMap::new(),
+            members: vec![],
+        };
+        for member in members {
+            let cell = grid.cell(points(member));
+            grid.cells.entry(cell).or_default().push(member);
+            grid.members.push(member);
         }
-        vertices_since_ear += 1;
+        grid
+    }
+    fn cell(&self, p: Vec2) -> (i32, i32) {
+        let c = ((p - self.origin) / self.cell_size).floor();
+        (c.x as i32, c.y as i32)
     }
-    // The remaining points form a convex polygon. First, let's verify that everything in is_convex is true.
-    //assert!(is_convex.values().all(|x| *x), "Not all points are convex");
-    // So we can use a basic algorithm to create triangles.
-    // It's low quality but we'll create a simple fan of triangles from the first point.
-    let mut keys = is_convex.into_keys();
-    let middle = keys.next().unwrap();
-    for (a, b) in keys.tuple_windows() {
-        triangles.push([middle, a, b]);
+    /// Whether any member within this box satisfies the predicate
+    fn any_within(&self, low: Vec2, high: Vec2, mut predicate: impl FnMut(usize) -> bool) -> bool {
+        let (low, high) = (self.cell(low), self.cell(high));
+        let cells = (high.0 - low.0 + 1) as i64 * (high.1 - low.1 + 1) as i64;
+        if cells > self.members.len() as i64 {
+            return self.members.iter().any(|&m| predicate(m));
+        }
+        (low.0..=high.0)
+            .cartesian_product(low.1..=high.1)
+            .filter_map(|cell| self.cells.get(&cell))
+            .flatten()
+            .any(|&m| predicate(m))
     }
+}
+
+/// Cut a simple counter-clockwise polygon into triangles, by repeatedly clipping off "ears":
+/// corners whose triangle contains no other part of the polygon.
+/// `sequence` lists indices into `points`; the triangles use the same indices.
+fn clip_ears(points: &[Vec2], sequence: &[usize]) -> Vec<[usize; 3]> {
+    let n = sequence.len();
+    if n < 3 {
+        return vec![];
+    }
+    let at = |node: usize| points[sequence[node]];
+    // A doubly linked ring of nodes, so clipping a corner is cheap
+    let mut prev = (0..n).map(|i| (i + n - 1) % n).collect_vec();
+    let mut next = (0..n).map(|i| (i + 1) % n).collect_vec();
+    let mut removed = vec![false; n];
+
+    // Only reflex (or flat) corners can poke into an ear, and corners never become reflex later
+    let corners = sequence
+        .iter()
+        .map(|&i| geo::Point::new(points[i].x, points[i].y))
+        .collect_vec();
+    let reflex = each_is_convex(&corners)
+        .into_iter()
+        .enumerate()
+        .filter(|(_, convex)| !convex)
+        .map(|(node, _)| node)
+        .collect_vec();
+    let reflex = PointGrid::new(at, reflex);
+
+    let mut triangles = vec![];
+    let mut emit = |a: usize, b: usize, c: usize| {
+        let mut triangle = [sequence[a], sequence[b], sequence[c]];
+        // Start from the lowest index so the output doesn't depend on where clipping started
+        let lowest = (0..3).min_by_key(|&i| triangle[i]).unwrap_or(0);
+        triangle.rotate_left(lowest);
+        triangles.push(triangle);
+    };
+    let is_ear = |a: usize, b: usize, c: usize, removed: &[bool]| {
+        let (pa, pb, pc) = (at(a), at(b), at(c));
+        if turn(pa, pb, pc) <= 0.0 {
+            return false;
+        }
+        let blocked = reflex.any_within(pa.min(pb).min(pc), pa.max(pb).max(pc), |node| {
+            let p = at(node);
+            // Bridges repeat points, and those copies don't count against the ear
+            !removed[node] && p != pa && p != pb && p != pc && point_in_triangle(p, pa, pb, pc)
+        });
+        !blocked
+    };
 
+    let mut remaining = n;
+    let mut tip = 1;
+    let mut failures = 0;
+    while remaining > 3 {
+        let (a, c) = (prev[tip], next[tip]);
+        let clip = if is_ear(a, tip, c, &removed) {
+            emit(a, tip, c);
+            Some(tip)
+        } else if failures >= remaining {
+            // We've gone all the way around without an ear, so the polygon is degenerate or
+            // intersects itself. Drop any corner that doesn't turn at all, which loses no area.
+            // Failing that, clip a convex corner anyway, so that we always finish.
+            let around = std::iter::successors(Some(tip), |&node| Some(next[node]))
+                .take(remaining)
+                .collect_vec();
+            let turns = |node: usize| turn(at(prev[node]), at(node), at(next[node]));
+            match around.iter().copied().find(|&node| turns(node) == 0.0) {
+                Some(flat) => Some(flat),
+                None => {
+                    let convex = around.iter().copied().find(|&node| turns(node) > 0.0).unwrap_or(tip);
+                    emit(prev[convex], convex, next[convex]);
+                    Some(convex)
+                }
+            }
+        } else {
+            None
+        };
+        match clip {
+            Some(node) => {
+                let (a, c) = (prev[node], next[node]);
+                next[a] = c;
+                prev[c] = a;
+                removed[node] = true;
+                remaining -= 1;
+                failures = 0;
+                // The corner before the ear has changed, so it may be an ear now
+                tip = a;
+            }
+            None => {
+                failures += 1;
+                tip = c;
+            }
+        }
+    }
+    let (a, c) = (prev[tip], next[tip]);
+    if turn(at(a), at(tip), at(c)) != 0.0 {
+        emit(a, tip, c);
+    }
     triangles
 }
 
+/// Cut a polygon into triangles, including any holes.
+/// The triangles index into the points from polygon_rings, and run counter-clockwise.
+/// Repeated points and points in a straight line are fine, and won't make slivers.
+fn triangles_from_polygon(polygon: &geo::Polygon<f32>) -> Vec<[usize; 3]> {
+    let rings = polygon_rings(polygon);
+    let points = rings.iter().flatten().copied().collect_vec();
+
+    let mut outline = None;
+    let mut holes = vec![];
+    let mut offset = 0;
+    for (i, ring) in rings.iter().enumerate() {
+        let mut indices = (offset..offset + ring.len()).collect_vec();
+        offset += ring.len();
+        indices.dedup_by(|a, b| points[*a] == points[*b]);
+        while indices.len() > 1 && points[indices[0]] == points[indices[indices.len() - 1]] {
+            indices.pop();
+        }
+        if indices.len() < 3 {
+            continue;
+        }
+        // The outline should run counter-clockwise, and the holes the other way
+        let ring_points = indices.iter().map(|&i| points[i]).collect_vec();
+        let is_hole = i > 0;
+        if (signed_area(&ring_points) > 0.0) == is_hole {
+            indices.reverse();
+        }
+        if is_hole {
+            holes.push(indices);
+        } else {
+            outline = Some(indices);
+        }
+    }
+    match outline {
+        Some(outline) => clip_ears(&points, &bridge_holes(&points, outline, holes)),
+        None => vec![],
+    }
+}
+
 /// Determine whether two triangles are equal, for tests.
 /// The vertices must come the same order, but the array may be rotated.
 fn triangles_equal(a: &[usize; 3], b: &[usize; 3]) -> bool {
@@ -292,11 +906,115 @@ fn test_triangles_from_polygon_on_a_concave_polygon() {
     assert_eq!(triangles[2], [0, 1, 4]);
 }
 
-/// Set up a series of meshes to represent the counties
-fn setup_counties(
+/// Check that the triangles cover the polygon exactly once: all wound the same way, without
+/// overlaps or gaps, so their areas add up to the polygon's
+#[cfg(test)]
+fn assert_covers(polygon: &geo::Polygon<f32>, triangles: &[[usize; 3]]) {
+    use geo::Area;
+    let points = polygon_rings(polygon).into_iter().flatten().collect_vec();
+    let mut total = 0.0;
+    for triangle in triangles {
+        let area = turn(points[triangle[0]], points[triangle[1]], points[triangle[2]]) / 2.0;
+        assert!(area >= 0.0, "Triangle {:?} is wound backward", triangle);
+        total += area as f64;
+    }
+    let expected = polygon.unsigned_area() as f64;
+    assert!(
+        (total - expected).abs() <= expected * 1e-3,
+        "Triangles cover {} but the polygon is {}",
+        total,
+        expected
+    );
+}
+
+/// Test triangles_from_polygon on a square with a square hole in it
+#[test]
+fn test_triangles_from_polygon_with_a_hole() {
+    let square = |low: f32, high: f32| {
+        geo::LineString::from(vec![(low, low), (high, low), (high, high), (low, high)])
+    };
+    let polygon = geo::Polygon::new(square(0.0, 3.0), vec![square(1.0, 2.0)]);
+    let triangles = triangles_from_polygon(&polygon);
+    // Eight points and one hole make eight triangles
+    assert_eq!(triangles.len(), 8);
+    assert_covers(&polygon, &triangles);
+    // The hole's points are numbered after the outline's
+    assert!(triangles.iter().flatten().any(|&i| i >= 4));
+
+    // Two holes, one wound the "wrong" way, with the outline running clockwise
+    let mut outline = geo::LineString::from(vec![(0.0f32, 0.0), (6.0, 0.0), (6.0, 3.0), (0.0, 3.0)]);
+    outline.0.reverse();
+    let mut backward = geo::LineString::from(vec![(4.0f32, 1.0), (5.0, 1.0), (5.0, 2.0), (4.0, 2.0)]);
+    backward.0.reverse();
+    let polygon = geo::Polygon::new(outline, vec![square(1.0, 2.0), backward]);
+    let triangles = triangles_from_polygon(&polygon);
+    assert_eq!(triangles.len(), 14);
+    assert_covers(&polygon, &triangles);
+}
+
+/// Test triangles_from_polygon on points that are repeated or in a straight line
+#[test]
+fn test_triangles_from_polygon_on_degenerate_points() {
+    let polygon = geo::Polygon::new(
+        geo::LineString::from(vec![
+            (0.0f32, 0.0),
+            (0.0, 0.0),
+            (1.0, 0.0),
+            (2.0, 0.0),
+            (2.0, 2.0),
+            (2.0, 2.0),
+            (1.0, 1.0),
+            (0.0, 2.0),
+        ]),
+        vec![],
+    );
+    let triangles = triangles_from_polygon(&polygon);
+    assert_covers(&polygon, &triangles);
+    // No slivers along the straight edge
+    assert_eq!(triangles.len(), 4);
+
+    // Everything in a line has no area to fill
+    let line = geo::Polygon::new(
+        geo::LineString::from(vec![(0.0f32, 0.0), (1.0, 1.0), (2.0, 2.0)]),
+        vec![],
+    );
+    assert!(triangles_from_polygon(&line).is_empty());
+}
+
+/// Test triangles_from_polygon on a comb, which has lots of concave corners
+#[test]
+fn test_triangles_from_polygon_on_a_comb() {
+    let mut points = vec![(0.0f32, 0.0)];
+    for tooth in 0..20 {
+        let x = tooth as f32;
+        points.extend([(x + 0.25, 0.0), (x + 0.25, 3.0), (x + 0.75, 3.0), (x + 0.75, 0.0)]);
+    }
+    points.extend([(20.0, 0.0), (20.0, -1.0), (0.0, -1.0)]);
+    // Reverse it, to make it counter-clockwise
+    points.reverse();
+    let polygon = geo::Polygon::new(geo::LineString::from(points), vec![]);
+    let triangles = triangles_from_polygon(&polygon);
+    assert_covers(&polygon, &triangles);
+}
+
+/// Test triangles_from_polygon on every state. Run it with --ignored once the map data is checked out.
+#[test]
+#[ignore = "needs the us-maps submodule checked out"]
+fn test_triangles_from_polygon_on_real_states() {
+    for state in GeoLayer::default().read().expect("Failed to read states") {
+        let triangles = triangles_from_polygon(&state.polygon);
+        assert!(!triangles.is_empty(), "{} has no triangles", state.name);
+        assert_covers(&state.polygon, &triangles);
+    }
+}
+
+/// Set up a series of meshes to represent the regions
+fn setup_regions(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
+    asset_server: Res<AssetServer>,
+    timeline: Option<Res<Timeline>>,
     mut map: Query<&mut USMap>,
 ) {
     if map.get_single().is_err() {
@@ -306,83 +1024,363 @@ fn setup_counties(
     } else {
         map.single_mut().loaded = true;
     }
-    let map = map.single();
-    for county in map.read_counties().expect("Failed to read counties") {
-        // We could simplify the polygons to accelerate rendering, but it seems unnecessary.
-        //.map(|poly| poly.simplifyvw_preserve(&0.0001))
-        let poly = &county.polygon;
-        let altitude = rand::random::<f32>() / 50.0;
-        // let tris = delaunator::triangulate(
-        //     &poly
-        //         .exterior()
-        //         .points()
-        //         .map(|p| delaunator::Point {
-        //             // These locations not displayed onscreen but for consistency we will scale them
-        //             x: -map.lon_pipe.apply(p.x()) as f64,
-        //             y: map.lat_pipe.apply(p.y()) as f64,
-        //         })
-        //         .collect::<Vec<_>>(),
-        // );
-        let tris = triangles_from_polygon(&poly)
-            .into_iter()
-            .flatten()
-            .collect_vec();
-
-        eprintln!(
-            "{}, {} points, {} triangles",
-            county.name,
-            poly.exterior().points().count(),
-            tris.len()
-        );
-        // write the obj file for debugging
-        // write_obj(
-        //     &poly.exterior().points().collect::<Vec<_>>(),
-        //     &tris[..],
-        //     &format!("us-maps/geojson/{}.obj", county.name),
-        // ).expect("Failed to write obj file");
-
-        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
-        let positions = poly
-            .exterior()
-            .points()
-            .map(|p| {
-                [
-                    map.lon_pipe.apply(p.x()),
-                    altitude,
-                    map.lat_pipe.apply(p.y()),
-                ]
-            })
-            .collect::<Vec<_>>();
-        let uv0_pipe = Pipe::new(-180.0..=180.0, 0.0..=1.0).overflow(Overflow::Saturate);
-        let uv0 = poly
-            .exterior()
-            .points()
-            .map(|p| [uv0_pipe.apply(p.x()), uv0_pipe.apply(p.y())])
-            .collect::<Vec<_>>();
-        let indices = tris.into_iter().map(|u| u as u32).rev().collect::<Vec<_>>();
-        let normals = estimate_vertex_normals(
-            &positions.iter().map(|v| Vec3::from(*v)).collect::<Vec<_>>(),
-            &indices,
-        )
-        .expect("Couldn't guess normals")
-        .into_iter()
-        .map(|v| v.to_array())
-        .collect::<Vec<_>>();
-        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
-        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
-        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uv0);
-        mesh.set_indices(Some(Indices::U32(indices)));
+    let mut map = map.single_mut();
+    let frame = timeline.map_or(0.0, |timeline| timeline.frame);
+    let regions = map.layer.read().expect("Failed to read map layer");
+    map.fit_to_values(&regions);
+    // Simplify every region together at each level, so neighbors agree on their borders
+    let polygons = regions.iter().map(|r| r.polygon.clone()).collect_vec();
+    let levels = map
+        .detail
+        .iter()
+        .map(|(_, tolerance)| simplify_shared(&polygons, *tolerance))
+        .collect_vec();
+    // Regions in several pieces get one label, on the largest piece
+    let mut largest: HashMap<&str, (usize, f32)> = HashMap::new();
+    for (i, region) in regions.iter().enumerate() {
+        let area = geo::Area::unsigned_area(&region.polygon);
+        let best = largest.entry(&region.name).or_insert((i, area));
+        if area > best.1 {
+            *best = (i, area);
+        }
+    }
+    let labeled = largest.into_values().collect::<HashMap<usize, f32>>();
+    let biggest = labeled.values().copied().fold(0.0, f32::max);
+    let mut bounds = (Vec3::splat(f32::INFINITY), Vec3::splat(f32::NEG_INFINITY));
+    let border_material = map.borders.map(|(_, color)| {
+        materials.add(StandardMaterial {
+            base_color: color,
+            // Borders should read the same from any angle, in light or shadow
+            unlit: true,
+            cull_mode: None,
+            ..Default::default()
+        })
+    });
+    for (i, county) in regions.into_iter().enumerate() {
+        // Project before triangulating, so the triangles are cut in the space they're shown in
+        let full = match map.flatten(&county.polygon) {
+            Some(poly) => poly,
+            None => {
+                debug!("{} can't be shown on this map", county.name);
+                continue;
+            }
+        };
+        let altitude = map.altitude_of(&county, frame);
+        let lift = map.border_lift();
+        let stretched = map.stretched_height();
+        // Stretched solids are all built to one height, and their outlines are lifted by a transform
+        // instead, so the lift isn't stretched along with them
+        let (top, border_height) = match stretched {
+            Some(top) => (top, top),
+            None => (altitude, altitude + lift),
+        };
+        let scale = stretched.map_or(1.0, |top| stretch(altitude, top));
+        // Every height the region's meshes use, so a point that can't be placed at one of them is caught up front
+        let heights = [0.0, top, border_height];
+        let placeable = levels.iter().all(|level| {
+            let poly = map.flatten(&level[i]).unwrap_or_else(|| full.clone());
+            polygon_rings(&poly)
+                .iter()
+                .flatten()
+                .all(|p| heights.iter().all(|&height| map.place(*p, height).is_some()))
+        });
+        if !placeable {
+            debug!("{} can't be placed on this surface", county.name);
+            continue;
+        }
+        let place = |p: Vec2, height: f32| map.place(p, height).expect("Every point was placed above");
+        let mut detail = Detail { meshes: vec![] };
+        let mut border_detail = Detail { meshes: vec![] };
+        for level in &levels {
+            // Simplified points are a subset of the originals, so they can be shown too
+            let poly = map.flatten(&level[i]).unwrap_or_else(|| full.clone());
+            let triangles = triangles_from_polygon(&poly);
+            debug!(
+                "{}, {} points, {} triangles",
+                county.name,
+                poly.exterior().points().count(),
+                triangles.len()
+            );
+            let rings = polygon_rings(&poly);
+            for p in rings.iter().flatten() {
+                for height in [0.0, top] {
+                    let placed = place(*p, height);
+                    bounds = (bounds.0.min(placed), bounds.1.max(placed));
+                }
+            }
+            // Stand each state up as a solid, so it has sides and casts a shadow
+            let mesh = extrude_polygon(&rings, &triangles, 0.0, top, place);
+            detail.meshes.push(meshes.add(mesh));
+            if let Some((width, _)) = map.borders {
+                let outline = outline_rings(&rings, border_height, width, place);
+                border_detail.meshes.push(meshes.add(outline));
+            }
+        }
 
         let material = materials.add(StandardMaterial {
-            base_color: county.color(),
-            alpha_mode: AlphaMode::Blend,
+            base_color: map.color_of(&county, frame),
+            // Blended materials don't cast shadows
+            alpha_mode: AlphaMode::Opaque,
             unlit: false,
             ..Default::default()
         });
-        commands.spawn().insert(county).insert_bundle(PbrBundle {
-            mesh: meshes.add(mesh),
-            material,
-            ..Default::default()
+        let label = map.labels.zip(labeled.get(&i)).and_then(|(height, &area)| {
+            let spot = label_spot(&full);
+            let anchor = map.place(Vec2::new(spot.x(), spot.y()), altitude + height)?;
+            let priority = Label::priority(area, biggest);
+            let label = Label::spawn(&mut commands, &asset_server, &county.name, anchor, height, priority);
+            Some((label, anchor))
         });
+        let mut region = commands.spawn();
+        region
+            .insert(map.tooltip_of(&county, frame))
+            .insert(county)
+            .insert_bundle(PbrBundle {
+                mesh: detail.meshes[map.level].clone(),
+                material,
+                transform: Transform::from_scale(Vec3::new(1.0, scale, 1.0)),
+                ..Default::default()
+            })
+            .insert(detail)
+            .insert(Altitude(altitude));
+        if let Some(material) = &border_material {
+            // The border is a child of the region, so it rises and falls with it
+            region.with_children(|region| {
+                region
+                    .spawn()
+                    .insert(Border)
+                    .insert_bundle(PbrBundle {
+                        mesh: border_detail.meshes[map.level].clone(),
+                        material: material.clone(),
+                        transform: Transform::from_xyz(0.0, stretched.map_or(0.0, |_| lift / scale), 0.0),
+                        ..Default::default()
+                    })
+                    .insert(border_detail);
+            });
+        }
+        let region = region.id();
+        if let Some((label, anchor)) = label {
+            commands.entity(label).insert(RegionLabel { region, anchor });
+        }
+    }
+    map.bounds = Some(bounds);
+}
+
+/// Where to write a region's name: its centroid, unless that falls outside of it
+fn label_spot(polygon: &geo::Polygon<f32>) -> geo::Point<f32> {
+    use geo::{Centroid, Contains, InteriorPoint};
+    match polygon.centroid() {
+        Some(centroid) if polygon.contains(&centroid) => centroid,
+        _ => polygon
+            .interior_point()
+            .unwrap_or_else(|| polygon.exterior().points().next().unwrap_or_default()),
+    }
+}
+
+/// A region's fill, and everything about it that changes over time
+type Region<'a> = (
+    Entity,
+    &'a State,
+    &'a Altitude,
+    &'a Handle<StandardMaterial>,
+    &'a mut Tooltip,
+    &'a mut Transform,
+);
+
+/// A region's outline, which is lifted by its own transform when the region is stretched
+type RegionBorder<'a> = (&'a Parent, &'a mut Transform);
+
+/// Recolor the regions as the Timeline plays, and raise or lower them if they're extruded
+fn animate_regions(
+    timeline: Option<Res<Timeline>>,
+    map: Query<&USMap>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut regions: Query<Region>,
+    mut borders: Query<RegionBorder, (With<Border>, Without<State>)>,
+    mut labels: Query<(&RegionLabel, &mut Label)>,
+) {
+    let (timeline, map) = match (timeline, map.get_single().ok()) {
+        (Some(timeline), Some(map)) if timeline.is_changed() && map.frames.len() > 1 => (timeline, map),
+        _ => return,
+    };
+    let stretched = map.stretched_height();
+    let lift = map.border_lift();
+    // How far each stretched region rose since it was built, and how much it's stretched now
+    let mut rises = HashMap::new();
+    for (entity, state, built, material, mut tooltip, mut transform) in regions.iter_mut() {
+        let color = map.color_of(state, timeline.frame);
+        // Only touch materials that change, since each one changed is sent to the GPU again
+        if materials.get(material).is_some_and(|m| m.base_color != color) {
+            if let Some(material) = materials.get_mut(material) {
+                material.base_color = color;
+            }
+        }
+        *tooltip = map.tooltip_of(state, timeline.frame);
+        if let Some(top) = stretched {
+            let altitude = map.altitude_of(state, timeline.frame);
+            transform.scale.y = stretch(altitude, top);
+            rises.insert(entity, (altitude - built.0, transform.scale.y));
+        }
+    }
+    for (parent, mut transform) in borders.iter_mut() {
+        if let Some((_, scale)) = rises.get(&parent.0) {
+            transform.translation.y = lift / scale;
+        }
+    }
+    for (region, mut label) in labels.iter_mut() {
+        if let Some((rise, _)) = rises.get(&region.region) {
+            label.anchor = region.anchor + Vec3::Y * *rise;
+        }
+    }
+}
+
+/// Show the level of detail that suits the camera's distance from the nearest part of the map
+fn switch_detail(
+    mut map: Query<&mut USMap>,
+    camera: Query<&GlobalTransform, With<PerspectiveProjection>>,
+    mut regions: Query<(&Detail, &mut Handle<Mesh>)>,
+) {
+    let (mut map, camera) = match (map.get_single_mut().ok(), camera.get_single().ok()) {
+        (Some(map), Some(camera)) => (map, camera),
+        _ => return,
+    };
+    let (low, high) = match map.bounds {
+        Some(bounds) => bounds,
+        None => return,
+    };
+    let distance = camera.translation.distance(camera.translation.clamp(low, high));
+    let level = map.level_near(distance);
+    if level == map.level {
+        return;
+    }
+    map.level = level;
+    for (detail, mut mesh) in regions.iter_mut() {
+        if let Some(handle) = detail.meshes.get(level) {
+            *mesh = handle.clone();
+        }
     }
 }
+
+/// Test reading a small layer with custom property names
+#[test]
+fn test_geolayer_read() {
+    let file = tempfile::Builder::new().suffix(".geo.json").tempfile().expect("Couldn't make a test layer");
+    let path = file.path();
+    std::fs::write(
+        path,
+        r#"{"type": "FeatureCollection", "features": [
+            {"type": "Feature", "properties": {"county": "Story", "fips": 19169, "pop": "98537"},
+             "geometry": {"type": "Polygon", "coordinates": [[[0, 0], [1, 0], [1, 1], [0, 0]]]}},
+            {"type": "Feature", "properties": {"county": "Polk", "fips": 19153},
+             "geometry": {"type": "MultiPolygon", "coordinates": [
+                [[[2, 0], [3, 0], [3, 1], [2, 0]]],
+                [[[4, 0], [5, 0], [5, 1], [4, 0]]]]}}
+        ]}"#,
+    )
+    .expect("Couldn't write test layer");
+    let regions = GeoLayer::new(path)
+        .name_key("county")
+        .id_key("fips")
+        .bind("pop")
+        .read()
+        .expect("Couldn't read test layer");
+    assert_eq!(regions.len(), 3);
+    assert_eq!(regions[0].name, "Story");
+    assert_eq!(regions[0].id.as_deref(), Some("19169"));
+    assert_eq!(regions[0].properties.get("pop"), Some(&98537.0));
+    assert!(regions[1].properties.is_empty());
+    assert!(regions[1..].iter().all(|r| r.name == "Polk"));
+
+    // The default name property isn't there
+    assert!(GeoLayer::new(path).read().is_err());
+}
+
+#[test]
+fn test_read_dated_values() {
+    let file = tempfile::NamedTempFile::new().expect("Couldn't make test values");
+    let path = file.path();
+    std::fs::write(path, r#"{"2021": {"Ohio": 2.0}, "2020": {"Ohio": 1.0, "Iowa": 3.0}}"#)
+        .expect("Couldn't write test values");
+    let dated = USMap::read_dated_values(path).expect("Couldn't read dated values");
+    assert_eq!(dated.len(), 2);
+    assert_eq!(dated["2020"].get("Iowa"), Some(&3.0));
+
+    // Plain values have no date
+    std::fs::write(path, r#"{"Ohio": 1.5}"#).expect("Couldn't write test values");
+    let dated = USMap::read_dated_values(path).expect("Couldn't read plain values");
+    assert_eq!(dated[""].get("Ohio"), Some(&1.5));
+}
+
+#[test]
+fn test_label_spot() {
+    use geo::Contains;
+    // The centroid of a U falls in the gap between its arms
+    let u = geo::Polygon::new(
+        geo::LineString::from(vec![
+            (0.0f32, 0.0),
+            (3.0, 0.0),
+            (3.0, 3.0),
+            (2.0, 3.0),
+            (2.0, 0.5),
+            (1.0, 0.5),
+            (1.0, 3.0),
+            (0.0, 3.0),
+            (0.0, 0.0),
+        ]),
+        vec![],
+    );
+    assert!(u.contains(&label_spot(&u)));
+    let square = geo::Polygon::new(
+        geo::LineString::from(vec![(0.0f32, 0.0), (2.0, 0.0), (2.0, 2.0), (0.0, 2.0), (0.0, 0.0)]),
+        vec![],
+    );
+    assert_eq!(label_spot(&square), geo::Point::new(1.0, 1.0));
+}
+
+#[test]
+fn test_level_near() {
+    let globe = Surface::Globe {
+        center: Vec3::ZERO,
+        radius: 1.0,
+        facing: (0.0, 0.0),
+    };
+    let mut map = USMap::new(globe).expect("Couldn't make a map");
+    map = map.detail(vec![(4.0, 0.0), (10.0, 0.02), (f32::INFINITY, 0.1)]);
+    assert_eq!(map.level_near(3.0), 0);
+    // Just past the edge isn't far enough to switch, either way
+    assert_eq!(map.level_near(4.2), 0);
+    map.level = 1;
+    assert_eq!(map.level_near(3.8), 1);
+    assert_eq!(map.level_near(3.5), 0);
+    assert_eq!(map.level_near(11.5), 2);
+}
+
+#[test]
+fn test_stretched_height() {
+    use crate::projection::{MapFrame, Projection};
+    let floor = MapFrame::fit(Projection::Equirectangular, -10.0..=10.0, 0.0..=10.0, &(-5.0..=5.0), &(-5.0..=5.0));
+    let frames = vec![HashMap::new(), HashMap::from([("Ohio".to_string(), 2.0)])];
+    let map = USMap::new(floor)
+        .expect("Couldn't make a map")
+        .choropleth_frames(frames)
+        .extrude(Pipe::new(0.0..=2.0, 0.0..=1.0));
+    let ohio = State {
+        name: "Ohio".into(),
+        id: None,
+        properties: HashMap::new(),
+        polygon: geo::Polygon::new(geo::LineString::from(Vec::<(f32, f32)>::new()), vec![]),
+    };
+    // Ohio has no value at first, so it starts out flat, but it still rises to its height later on
+    let top = map.stretched_height().expect("Flat maps stretch their solids");
+    assert_eq!(map.altitude_of(&ohio, 0.0), 0.0);
+    assert!(stretch(map.altitude_of(&ohio, 0.0), top) > 0.0);
+    assert_eq!(stretch(map.altitude_of(&ohio, 1.0), top), 1.0);
+    // Solids on a globe are built as tall as they stand
+    let globe = Surface::Globe {
+        center: Vec3::ZERO,
+        radius: 1.0,
+        facing: (0.0, 0.0),
+    };
+    let map = USMap::new(globe).expect("Couldn't make a map").extrude(Pipe::new(0.0..=2.0, 0.0..=1.0));
+    assert_eq!(map.stretched_height(), None);
+}
@@ -1,2 +1,3 @@
 pub mod reliefmap;
+pub mod scatter;
 pub mod wordcloud;
@@ -1,60 +1,346 @@
+use crate::capture::Capture;
 use crate::errors::*;
-use crate::feature::{Feature, Overflow, Pipe};
-use crate::usmap::USMap;
-use anyhow::*;
+use crate::feature::{Feature, Pipe, Scale};
+use crate::labels::LabelPlugin;
+use crate::palette::Palette;
+use crate::picking::PickingPlugin;
+use crate::scatterplot::{Glyph, Scatterplot, ScatterplotSystem, INSTANCING_THRESHOLD};
+use crate::projection::{MapFrame, Projection, Surface};
+use crate::stream::{Incoming, Source, StreamPlugin, Streamed};
+use crate::table::{Columns, Series, Table};
+use crate::theater::{Axis, Theater};
+use crate::timeline::Timeline;
+use crate::usmap::{GeoLayer, USMap};
+use anyhow::anyhow;
 use bevy::prelude::*;
-use serde::Deserialize;
+use itertools::{izip, Itertools};
+use std::collections::HashMap;
 
-#[derive(Debug, Deserialize)]
-struct ScatterPoint {
-    lat: f32,
-    lon: f32,
-    alt: f32,
-    size: f32,
+/// What the map should show, from the command line
+#[derive(Debug, Clone)]
+pub struct MapSettings {
+    /// How to flatten the globe, for both the regions and the cities
+    pub projection: Projection,
+    /// The regions to draw
+    pub layer: GeoLayer,
+    /// A JSON file of choropleth values, keyed by region name or id, and optionally by date first
+    pub values: Option<std::path::PathBuf>,
+    /// A property of the layer to use for the choropleth instead
+    pub value_key: Option<String>,
+    /// Raise each region by its value
+    pub extrude: bool,
+    /// Wrap everything around a globe instead of projecting it onto the floor
+    pub globe: bool,
+    /// How wide to draw the borders between regions, or 0 for none
+    pub border_width: f32,
+    /// Write the names of the regions and cities
+    pub labels: bool,
+    /// A table of points to show over the map: CSV, JSON lines, or a JSON array
+    pub points: std::path::PathBuf,
+    /// Which columns of the table to use for what
+    pub columns: Columns,
+    /// Draw the points in one batch, even if there aren't many. They can't be picked.
+    pub instanced: bool,
+    /// The shape of every point, unless the columns choose one per point.
+    /// Columns make a column map, with the altitude as each bar's height.
+    pub glyph: Glyph,
+    /// Where to read more points from while the app runs, one JSON object per line.
+    /// A row with the label (and time) of an earlier one replaces it.
+    pub stream: Option<Source>,
+    /// Write images instead of opening a window
+    pub capture: Option<Capture>,
 }
 
-pub fn main() -> Result<()> {
-    let theater = crate::theater::Theater::default();
-    let lat_pipe = Pipe::new(71.0..=25.0, -2.0..=2.0);
-    let lon_pipe = Pipe::new(-180.0..=65.0, -8.0..=8.0);
+impl Default for MapSettings {
+    fn default() -> Self {
+        MapSettings {
+            projection: Projection::AlbersUsa,
+            layer: GeoLayer::default(),
+            values: None,
+            value_key: None,
+            extrude: false,
+            globe: false,
+            border_width: 0.01,
+            labels: false,
+            points: "points.json".into(),
+            columns: Columns::default(),
+            instanced: false,
+            glyph: Glyph::Sphere,
+            stream: None,
+            capture: None,
+        }
+    }
+}
 
-    let points: Vec<ScatterPoint> = serde_json::from_slice(&std::fs::read("points.json")?)?;
+/// MapSettings, with the values read from disk
+struct MapSetup {
+    settings: MapSettings,
+    surface: Surface,
+    /// Choropleth values at each frame of the Timeline, or just one set if they don't change
+    values: Vec<HashMap<String, f32>>,
+    /// The choropleth values as they were read, by date, to fill in dates that stream in later
+    dated: HashMap<String, HashMap<String, f32>>,
+}
 
-    let lats = points.iter().map(|p| p.lat).collect::<Vec<_>>();
-    let lats = lat_pipe.clone().bundle(lats);
+/// Show cities over a map, optionally as a choropleth of per-region values
+pub fn main(settings: MapSettings) -> Result<()> {
+    let theater = Theater::default();
+    // The cities and the map share one surface, so they line up
+    let surface: Surface = if settings.globe {
+        // Fill the Theater's height, resting on the floor, with the US facing the front
+        let radius = (theater.height.end() - theater.height.start()) / 2.0;
+        Surface::Globe {
+            center: Vec3::new(0.0, theater.height.start() + radius, 0.0),
+            radius,
+            facing: (-98.0, 39.0),
+        }
+    } else {
+        MapFrame::fit(
+            settings.projection,
+            -180.0..=-65.0,
+            18.0..=72.0,
+            &theater.width,
+            &theater.depth,
+        )
+        .into()
+    };
 
-    let lons = points.iter().map(|p| p.lon).collect::<Vec<_>>();
-    let lons = lon_pipe.clone().bundle(lons);
+    let table = Table::read(&settings.points)?;
+    let columns = &settings.columns;
 
-    let alts = points.iter().map(|p| p.alt).collect::<Vec<_>>();
-    let alts = Pipe::from(&alts[..]).fit_to(&(0.1..=1.0)).bundle(alts);
+    // Dated choropleth values and a time column share one Timeline
+    let dated = match &settings.values {
+        Some(path) => USMap::read_dated_values(path)?,
+        None => HashMap::new(),
+    };
+    let mut dates = dated.keys().filter(|date| !date.is_empty()).cloned().collect_vec();
+    if let Some(time) = &columns.time {
+        dates.extend(table.texts(time)?);
+    }
+    let timeline = (!dates.is_empty()).then(|| Timeline::new(dates));
+    let values = match &timeline {
+        Some(timeline) => values_on(&dated, &timeline.dates),
+        None => dated.get("").cloned().into_iter().collect_vec(),
+    };
 
-    let sizes = points.iter().map(|p| p.size).collect::<Vec<_>>();
-    let sizes = Pipe::from(&sizes[..]).fit_to(&(0.01..=0.05)).bundle(sizes);
+    let plot = match plot_points(&settings, &surface, &theater, &table, timeline.as_ref()) {
+        Ok(plot) => plot,
+        // A stream can start from nothing, with the points arriving later
+        Err(_) if settings.stream.is_some() && table.is_empty() => Scatterplot::default(),
+        Err(error) => return Err(error),
+    };
 
-    let colors = points
-        .iter()
-        .map(|p| Color::rgb(p.size, p.size, p.size))
-        .collect::<Vec<_>>();
-    App::new()
-        .add_plugin(crate::scatterplot::Scatterplot {
-            lats,
-            lons,
-            alts,
-            sizes,
-            colors
-        })
+    let stream = settings.stream.clone();
+    let mut app = App::new();
+    if let Some(capture) = settings.capture.clone() {
+        app.add_plugin(capture);
+    }
+    // The Theater sets up rendering, which the points need to be there already
+    app.add_plugin(theater)
+        .add_plugin(plot)
         .add_plugin(crate::usmap::USMapPlugin)
-        .add_plugin(theater)
-        .add_startup_system(setup_map)
-        .run();
+        .add_plugin(PickingPlugin)
+        .add_plugin(LabelPlugin)
+        .insert_resource(MapSetup {
+            settings,
+            surface,
+            values,
+            dated,
+        })
+        .add_startup_system(setup_map);
+    if let Some(timeline) = timeline {
+        app.add_plugin(timeline);
+    }
+    if let Some(source) = stream {
+        app.insert_resource(Incoming::open(&source)?)
+            .add_plugin(StreamPlugin)
+            .insert_resource(Plotted(table))
+            .add_system(take_rows.before(ScatterplotSystem::Sync));
+    }
+    app.run();
     Ok(())
 }
 
-fn setup_map(mut commands: Commands) {
-    let lat_pipe = Pipe::new(71.0..=25.0, -2.0..=2.0).overflow(Overflow::Saturate);
-    let lon_pipe = Pipe::new(-180.0..=65.0, -8.0..=8.0).overflow(Overflow::Saturate);
-    println!("lon_pipe: {:?}, lat_pipe: {:?}", lon_pipe, lat_pipe);
-    let map = USMap::new(lon_pipe, lat_pipe).expect("Failed to load US map");
+/// The choropleth values on each date. A region keeps its last value until it has a new one.
+fn values_on(dated: &HashMap<String, HashMap<String, f32>>, dates: &[String]) -> Vec<HashMap<String, f32>> {
+    let mut current = dated.get("").cloned().unwrap_or_default();
+    dates
+        .iter()
+        .map(|date| {
+            current.extend(dated.get(date).into_iter().flatten().map(|(k, &v)| (k.clone(), v)));
+            current.clone()
+        })
+        .collect()
+}
+
+/// Place the table's points over the map, moving along the Timeline if they have times
+fn plot_points(
+    settings: &MapSettings,
+    surface: &Surface,
+    theater: &Theater,
+    table: &Table,
+    timeline: Option<&Timeline>,
+) -> Result<Scatterplot> {
+    let columns = &settings.columns;
+    let label = columns.label_in(table);
+
+    // With a time column, each row is one city at one time, and cities are told apart by label
+    let series = match (&columns.time, timeline) {
+        (Some(time), Some(timeline)) => {
+            let id = label.ok_or_else(|| anyhow!("Animated points need a label column to tell them apart"))?;
+            table.series(time, id, &timeline.dates)?
+        }
+        _ => Series::still(table),
+    };
+    let count = series.ids.len();
+    // Anything that doesn't change comes from each point's first row
+    let first = table.select(&series.first_rows());
+
+    let lats = series.frames(&table.numbers(&columns.lat)?, f32::NAN);
+    let lons = series.frames(&table.numbers(&columns.lon)?, f32::NAN);
+    let alts = series.frames(&columns.numbers_or(table, &columns.alt, 0.0)?, f32::NAN);
+    let sizes = series.frames(&columns.numbers_or(table, &columns.size, 0.0)?, f32::NAN);
+    let names = match label {
+        Some(label) => first.texts(label)?,
+        None => vec![String::new(); count],
+    };
+    let attributes = first.attributes_except(&columns.used());
+
+    let shown = columns.used().into_iter().filter(|c| Some(*c) != label).collect_vec();
+    let tooltips = first.tooltips(label, &shown);
+    let labels = if settings.labels { names.clone() } else { vec![] };
+
+    let glyphs = match &columns.glyph {
+        Some(column) if first.has(column) => first.glyphs(column)?,
+        _ => vec![settings.glyph; count],
+    };
+    let alt_pipe = if settings.glyph == Glyph::Column && columns.glyph.is_none() {
+        // Bars rise from the ground, so their heights have to be in proportion to the altitudes
+        let top = alts.iter().flatten().copied().filter(|a| a.is_finite()).fold(0.0, f32::max);
+        Pipe::new(0.0..=top, theater.part(Axis::Height, 0.0..=0.4))
+    } else {
+        // Points float a little above the map, however tall the Theater is
+        Pipe::from(&alts.concat()[..]).fit_to(&theater.part(Axis::Height, 0.02..=0.2))
+    };
+    // Place the points at every frame, then gather each axis across the frames
+    let placed = izip!(&lons, &lats, &alts)
+        .map(|(lons, lats, alts)| surface.features(lons, lats, &alt_pipe.clone().bundle(alts.clone()).convert()))
+        .collect_vec();
+    let (x, y, z) = placed[0].clone();
+    let x = x.frames(placed.iter().map(|(x, _, _)| x.content.clone()).collect());
+    let y = y.frames(placed.iter().map(|(_, y, _)| y.content.clone()).collect());
+    let z = z.frames(placed.iter().map(|(_, _, z)| z.content.clone()).collect());
+    // Columns rise from the ground under wherever their points are at each frame
+    let base_frames = izip!(&lons, &lats)
+        .map(|(lons, lats)| {
+            let (x, y, z) = surface.features(lons, lats, &vec![0.0; count]);
+            izip!(x.convert(), y.convert(), z.convert())
+                .map(|(x, y, z)| Vec3::new(x, y, z))
+                .collect_vec()
+        })
+        .collect_vec();
+
+    let color_frames = match &columns.color {
+        Some(column) => series.frames(&table.colors(column)?, Color::GRAY),
+        None => {
+            let scale = Palette::viridis()
+                .scale(Pipe::new(1.0..=10.0, 0.0..=1.0).scale(Scale::Log(10.0)).infer_domain(&sizes.concat()));
+            sizes.iter().map(|sizes| scale.convert(sizes)).collect_vec()
+        }
+    };
+    // Populations span several orders of magnitude, so size by area rather than radius
+    let sizes = Pipe::new(0.0..=1.0, 0.0..=1.0)
+        .scale(Scale::Pow(0.5))
+        .infer_domain(&sizes.concat())
+        .fit_to(&(0.01..=0.05))
+        .bundle(vec![])
+        .frames(sizes);
+
+    Ok(Scatterplot {
+        x,
+        y,
+        z,
+        sizes,
+        colors: color_frames[0].clone(),
+        color_frames,
+        tooltips,
+        labels,
+        names,
+        attributes,
+        instanced: settings.instanced || count > INSTANCING_THRESHOLD,
+        glyphs,
+        bases: base_frames[0].clone(),
+        base_frames,
+    })
+}
+
+/// Every row plotted so far, including the ones that streamed in
+struct Plotted(Table);
+
+/// Take in streamed rows and place the points again. The Scatterplot moves its points to match.
+/// Rows on new dates add them to the Timeline, and the choropleth carries its values over to them.
+fn take_rows(
+    mut streamed: EventReader<Streamed>,
+    setup: Res<MapSetup>,
+    theater: Res<Theater>,
+    mut timeline: Option<ResMut<Timeline>>,
+    mut maps: Query<&mut USMap>,
+    mut plotted: ResMut<Plotted>,
+    mut plot: ResMut<Scatterplot>,
+) {
+    let columns = &setup.settings.columns;
+    // Cities are told apart by label, and by time too if they move
+    let key = [columns.label.as_deref(), columns.time.as_deref()].into_iter().flatten().collect_vec();
+    let mut changed = false;
+    for Streamed(row) in streamed.iter() {
+        plotted.0.upsert(row.clone(), &key);
+        changed = true;
+    }
+    if !changed {
+        return;
+    }
+    if let (Some(timeline), Some(time)) = (timeline.as_mut(), &columns.time) {
+        let times = plotted.0.texts(time).unwrap_or_default();
+        let dates = Timeline::new(timeline.dates.iter().cloned().chain(times)).dates;
+        if timeline.dates != dates {
+            // The map has a frame for each date if it has any values
+            if !setup.values.is_empty() {
+                for mut map in maps.iter_mut() {
+                    map.set_choropleth_frames(values_on(&setup.dated, &dates));
+                }
+            }
+            timeline.dates = dates;
+        }
+    }
+    match plot_points(&setup.settings, &setup.surface, &theater, &plotted.0, timeline.as_deref()) {
+        // Whether points are instanced is settled when the plugin is added
+        Ok(replot) => {
+            *plot = Scatterplot {
+                instanced: plot.instanced,
+                ..replot
+            }
+        }
+        Err(error) => warn!("Can't place the streamed rows yet: {}", error),
+    }
+}
+
+fn setup_map(mut commands: Commands, setup: Res<MapSetup>, theater: Res<Theater>) {
+    let settings = &setup.settings;
+    let mut map = USMap::new(setup.surface.clone())
+        .expect("Failed to load US map")
+        .layer(settings.layer.clone());
+    if !setup.values.is_empty() {
+        map = map.choropleth_frames(setup.values.clone());
+    }
+    if let Some(key) = &settings.value_key {
+        map = map.choropleth_property(key);
+    }
+    if settings.extrude {
+        map = map.extrude_to(theater.part(Axis::Height, 0.01..=0.1));
+    }
+    map = map.borders(settings.border_width, Color::rgb(0.1, 0.1, 0.1));
+    if settings.labels {
+        map = map.labels(0.08);
+    }
     commands.spawn().insert(map);
 }
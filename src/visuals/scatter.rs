@@ -0,0 +1,234 @@
+use crate::capture::Capture;
+use crate::errors::*;
+use crate::feature::{Pipe, Scale};
+use crate::labels::LabelPlugin;
+use crate::palette::Palette;
+use crate::picking::PickingPlugin;
+use crate::scatterplot::{Glyph, Scatterplot, ScatterplotSystem, INSTANCING_THRESHOLD};
+use crate::stream::{Incoming, Source, StreamPlugin, Streamed};
+use crate::table::{Series, Table};
+use crate::theater::{Axis, Theater};
+use crate::timeline::Timeline;
+use bevy::prelude::*;
+use itertools::Itertools;
+
+/// What the scatterplot should show, from the command line
+#[derive(Debug, Clone)]
+pub struct ScatterSettings {
+    /// A table of points: CSV, JSON lines, or a JSON array.
+    /// It can be left out if the points are streamed in instead.
+    pub points: Option<std::path::PathBuf>,
+    /// The column spread across the Theater's width
+    pub x: String,
+    /// The column spread up the Theater's height
+    pub y: String,
+    /// The column spread through the Theater's depth
+    pub z: String,
+    /// Without it, every point is the same size
+    pub size: Option<String>,
+    /// Numbers get a gradient and text gets a color per category
+    pub color: Option<String>,
+    /// A name or id for each point
+    pub label: Option<String>,
+    /// Write each point's label next to it
+    pub labels: bool,
+    /// Draw the points in one batch, even if there aren't many. They can't be picked.
+    pub instanced: bool,
+    /// The shape of every point, unless glyph_by chooses one per point.
+    /// Columns rise from the floor.
+    pub glyph: Glyph,
+    /// Glyph names, or categories to give each their own glyph
+    pub glyph_by: Option<String>,
+    /// A date or time for each row, to animate points listed once per time.
+    /// Points are then told apart by their label.
+    pub time: Option<String>,
+    /// Where to read more rows from while the app runs, one JSON object per line.
+    /// A row with the label (and time) of an earlier one replaces it.
+    pub stream: Option<Source>,
+    /// Write images instead of opening a window
+    pub capture: Option<Capture>,
+}
+
+impl Default for ScatterSettings {
+    fn default() -> Self {
+        ScatterSettings {
+            points: Some("points.csv".into()),
+            x: "x".into(),
+            y: "y".into(),
+            z: "z".into(),
+            size: None,
+            color: None,
+            label: Some("name".into()),
+            labels: false,
+            instanced: false,
+            glyph: Glyph::Sphere,
+            glyph_by: None,
+            time: None,
+            stream: None,
+            capture: None,
+        }
+    }
+}
+
+/// Show any three columns of a table as points in the Theater, with no geography involved
+pub fn main(settings: ScatterSettings) -> Result<()> {
+    let theater = Theater::default();
+    let table = match &settings.points {
+        Some(path) => Table::read(path)?,
+        None => Table::default(),
+    };
+    let (plot, timeline) = match plot_table(&settings, &table) {
+        Ok(plotted) => plotted,
+        // A stream can start from nothing, with the points arriving later
+        Err(_) if settings.stream.is_some() && table.is_empty() => {
+            (Scatterplot::default(), settings.time.as_ref().map(|_| Timeline::new(vec![])))
+        }
+        Err(error) => return Err(error),
+    };
+
+    let mut app = App::new();
+    if let Some(capture) = settings.capture.clone() {
+        app.add_plugin(capture);
+    }
+    // The Theater sets up rendering, which the plot needs to be there already
+    app.add_plugin(theater)
+        .add_plugin(plot)
+        .add_plugin(PickingPlugin)
+        .add_plugin(LabelPlugin);
+    if let Some(timeline) = timeline {
+        app.add_plugin(timeline);
+    }
+    if let Some(source) = settings.stream.clone() {
+        app.insert_resource(Incoming::open(&source)?)
+            .add_plugin(StreamPlugin)
+            .insert_resource(Plotted(table))
+            .insert_resource(settings)
+            .add_system(take_rows.before(ScatterplotSystem::Sync));
+    }
+    app.run();
+    Ok(())
+}
+
+/// Plot a table as the settings say, with a Timeline if the points move over time
+fn plot_table(settings: &ScatterSettings, table: &Table) -> Result<(Scatterplot, Option<Timeline>)> {
+    let label = settings.label.as_deref().filter(|column| table.has(column));
+
+    // With a time column, each row is one point at one time, and points are told apart by label
+    let (series, timeline) = match &settings.time {
+        Some(time) => {
+            let id = label.ok_or_else(|| anyhow::anyhow!("Animated points need a label column to tell them apart"))?;
+            let timeline = Timeline::new(table.texts(time)?);
+            if timeline.dates.is_empty() {
+                anyhow::bail!("The time column {} has no times in it", time);
+            }
+            (table.series(time, id, &timeline.dates)?, Some(timeline))
+        }
+        None => (Series::still(table), None),
+    };
+    let count = series.ids.len();
+    // Anything that doesn't change comes from each point's first row
+    let first = table.select(&series.first_rows());
+
+    // Each column stretches to fill its side of the Theater
+    let axis = |column: &str, axis: Axis| -> Result<_> {
+        let frames = series.frames(&table.numbers(column)?, f32::NAN);
+        Ok(Pipe::from(&frames.concat()[..]).bundle(vec![]).frames(frames).on(axis))
+    };
+    let x = axis(&settings.x, Axis::Width)?;
+    let y = axis(&settings.y, Axis::Height)?;
+    let z = axis(&settings.z, Axis::Depth)?;
+
+    let sizes = match &settings.size {
+        Some(column) => series.frames(&table.numbers(column)?, f32::NAN),
+        None => vec![vec![0.0; count]],
+    };
+    let sizes = Pipe::new(0.0..=1.0, 0.0..=1.0)
+        .scale(Scale::Pow(0.5))
+        .infer_domain(&sizes.concat())
+        .fit_to(&(0.02..=0.08))
+        .bundle(vec![])
+        .frames(sizes);
+    let color_frames = match &settings.color {
+        Some(column) => series.frames(&table.colors(column)?, Color::GRAY),
+        None => vec![vec![Palette::viridis().sample(0.5); count]],
+    };
+
+    let glyphs = match &settings.glyph_by {
+        Some(column) => first.glyphs(column)?,
+        None => vec![settings.glyph; count],
+    };
+
+    let names = match label {
+        Some(column) => first.texts(column)?,
+        None => vec![String::new(); count],
+    };
+    let used = [
+        Some(&settings.x),
+        Some(&settings.y),
+        Some(&settings.z),
+        settings.size.as_ref(),
+        settings.time.as_ref(),
+    ]
+    .into_iter()
+    .flatten()
+    .map(|column| column.as_str())
+    .collect_vec();
+    let tooltips = first.tooltips(label, &used);
+    let attributes = first.attributes_except(&used.iter().copied().chain(label).collect_vec());
+    let labels = if settings.labels { names.clone() } else { vec![] };
+
+    let plot = Scatterplot {
+        x,
+        y,
+        z,
+        sizes,
+        colors: color_frames[0].clone(),
+        color_frames,
+        tooltips,
+        labels,
+        names,
+        attributes,
+        instanced: settings.instanced || count > INSTANCING_THRESHOLD,
+        glyphs,
+        ..Scatterplot::default()
+    };
+    Ok((plot, timeline))
+}
+
+/// Every row plotted so far, including the ones that streamed in
+struct Plotted(Table);
+
+/// Take in streamed rows and plot everything again. The Scatterplot moves its points to match.
+fn take_rows(
+    mut streamed: EventReader<Streamed>,
+    settings: Res<ScatterSettings>,
+    mut plotted: ResMut<Plotted>,
+    mut plot: ResMut<Scatterplot>,
+    timeline: Option<ResMut<Timeline>>,
+) {
+    // Points are told apart by label, and by time too if they move
+    let key = [settings.label.as_deref(), settings.time.as_deref()].into_iter().flatten().collect_vec();
+    let mut changed = false;
+    for Streamed(row) in streamed.iter() {
+        plotted.0.upsert(row.clone(), &key);
+        changed = true;
+    }
+    if !changed {
+        return;
+    }
+    match plot_table(&settings, &plotted.0) {
+        Ok((replot, retimed)) => {
+            // Whether points are instanced is settled when the plugin is added
+            *plot = Scatterplot {
+                instanced: plot.instanced,
+                ..replot
+            };
+            if let (Some(mut timeline), Some(retimed)) = (timeline, retimed) {
+                if timeline.dates != retimed.dates {
+                    timeline.dates = retimed.dates;
+                }
+            }
+        }
+        Err(error) => warn!("Can't plot the streamed rows yet: {}", error),
+    }
+}
@@ -1,7 +1,11 @@
+use crate::capture::Capture;
 use crate::errors::Result;
+use crate::labels::{lock_rotations, RotateLock};
+use crate::scatterplot::Attribute;
+use crate::stream::{Incoming, Source, StreamPlugin, Streamed};
+use crate::table::Row;
 
 use bevy::prelude::*;
-use bevy::render::camera::Camera;
 use bevy_text_mesh::prelude::*;
 use rand::prelude::*;
 use serde::Deserialize;
@@ -17,12 +21,36 @@ struct WordParams {
     size: f32,
     category: String,
 }
+impl WordParams {
+    /// Read a word from a streamed row, like {"text": "hello", "size": 3, "category": "greetings"}
+    fn from_row(row: &Row) -> Option<Self> {
+        let text = |key: &str| match row.get(key)? {
+            Attribute::Missing => None,
+            value => Some(value.to_string()),
+        };
+        let size = match row.get("size")? {
+            Attribute::Number(size) => *size,
+            _ => return None,
+        };
+        Some(WordParams {
+            text: text("text")?,
+            size,
+            category: text("category").unwrap_or_default(),
+        })
+    }
+}
 
 /// All the configuration for a cloud, readable as a JSON file
 #[derive(Debug, Clone, Deserialize)]
 pub struct WordCloudVisual {
     title: String,
     words: Vec<WordParams>,
+    /// Where to read more words from while the app runs
+    #[serde(skip)]
+    stream: Option<Source>,
+    /// Write images instead of opening a window
+    #[serde(skip)]
+    capture: Option<Capture>,
 }
 impl WordCloudVisual {
     pub fn new(word_info: &std::path::Path) -> Result<Self> {
@@ -30,20 +58,41 @@ impl WordCloudVisual {
         Ok(serde_json::from_reader(file)?)
     }
 
+    /// Read more words from a stream while the app runs. Words already in the cloud are resized.
+    pub fn stream(mut self, source: Source) -> Self {
+        self.stream = Some(source);
+        self
+    }
+
+    /// Write images of the cloud instead of opening a window
+    pub fn capture(mut self, capture: Capture) -> Self {
+        self.capture = Some(capture);
+        self
+    }
+
     pub fn start(self) -> Result<()> {
-        App::new()
-            .insert_resource(bevy_atmosphere::AtmosphereMat::default())
+        let stream = self.stream.clone();
+        let mut app = App::new();
+        match self.capture.clone() {
+            Some(capture) => app.add_plugin(capture),
+            None => app.add_plugins(DefaultPlugins),
+        };
+        app.insert_resource(bevy_atmosphere::AtmosphereMat::default())
             .insert_resource(Msaa { samples: 4 })
             .insert_resource(self)
-            .add_plugins(DefaultPlugins)
             .add_plugin(TextMeshPlugin)
             .add_plugin(bevy_atmosphere::AtmospherePlugin { dynamic: false, sky_radius: 100.0 })
             .add_plugin(bevy_fly_camera::FlyCameraPlugin)
             .add_startup_system(setup_background)
             .add_startup_system(setup_cloud)
             .add_system(lock_rotations)
-            .add_system(scoot_words)
-            .run();
+            .add_system(scoot_words);
+        if let Some(source) = stream {
+            app.insert_resource(Incoming::open(&source)?)
+                .add_plugin(StreamPlugin)
+                .add_system(take_words);
+        }
+        app.run();
         Ok(())
     }
 }
@@ -54,10 +103,6 @@ struct CloudState {
     category: Option<String>,
 }
 
-/// Rotate this entity to always point to the camera
-#[derive(Component)]
-struct RotateLock;
-
 /// Any text that is part of the word cloud
 #[derive(Component)]
 struct Word;
@@ -67,6 +112,11 @@ struct Word;
 struct Legend;
 
 impl Word {
+    /// How big to draw a word of some size
+    fn scale(size: f32) -> Vec3 {
+        Vec3::ONE * size.exp().sqrt() / 500.0
+    }
+
     /// Add a word in any random direction
     fn add(
         commands: &mut Commands,
@@ -82,7 +132,7 @@ impl Word {
                 rng.gen_range(0.0..4.0),
                 rng.gen_range(-2.0..2.0),
             ),
-            scale: Vec3::ONE * size.exp().sqrt() / 500.0,
+            scale: Word::scale(size),
             ..Default::default()
         };
         let color = fasthash::city::hash32(text.as_bytes()).to_be_bytes();
@@ -125,12 +175,11 @@ fn setup_cloud(
 ) {
     let state = CloudState {
         font: asset_server.load("fonts/FiraSans-Bold.ttf"),
-        // Any category
-        category: Some(
-            visual.words[rand::random::<usize>() % visual.words.len()]
-                .category
-                .clone(),
-        ),
+        // Any category, or the first to stream in if there aren't any words yet
+        category: visual
+            .words
+            .choose(&mut rand::thread_rng())
+            .map(|word| word.category.clone()),
     };
 
     commands
@@ -166,17 +215,36 @@ fn setup_cloud(
     commands.insert_resource(state);
 }
 
-/// Keep the legend pointing at the camera all the time
-fn lock_rotations(
-    mut transform_pair: ParamSet<(
-        Query<&Transform, With<Camera>>,
-        Query<&mut Transform, With<RotateLock>>,
-    )>,
+/// Resize words that stream in again, and add new ones in the cloud's category
+fn take_words(
+    mut streamed: EventReader<Streamed>,
+    mut commands: Commands,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut state: ResMut<CloudState>,
+    mut words: Query<(&TextMesh, &mut Transform), With<Word>>,
 ) {
-    let camera_translation = transform_pair.p0().single().translation;
-    for mut locked_transform in transform_pair.p1().iter_mut() {
-        // eh - why negative?
-        *locked_transform = locked_transform.looking_at(-camera_translation, Vec3::Y);
+    // Only the latest size counts when a word arrives more than once
+    let mut arrivals = std::collections::HashMap::new();
+    for Streamed(row) in streamed.iter() {
+        let word = match WordParams::from_row(row) {
+            Some(word) => word,
+            None => {
+                warn!("Skipping a streamed word without text and a numeric size");
+                continue;
+            }
+        };
+        let category = state.category.get_or_insert_with(|| word.category.clone());
+        if *category == word.category {
+            arrivals.insert(word.text.trim().to_string(), word.size);
+        }
+    }
+    for (mesh, mut transform) in words.iter_mut() {
+        if let Some(size) = arrivals.remove(&mesh.text) {
+            transform.scale = Word::scale(size);
+        }
+    }
+    for (text, size) in arrivals {
+        Word::add(&mut commands, materials.as_mut(), &state.font, &text, size);
     }
 }
 